@@ -0,0 +1,386 @@
+use std::{fmt::Write, io, path::PathBuf, str::FromStr, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::networking::{
+    payload::{self, MessageId},
+    reactions::{self, Reaction},
+    Message, MessageType,
+};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" | "htm" => Ok(ExportFormat::Html),
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            _ => Err(format!(
+                "unknown export format '{}', expected md, html or jsonl",
+                format
+            )),
+        }
+    }
+}
+
+// One line of a JSON Lines export, also what the CLI reads back in
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    #[serde(default = "payload::new_id")]
+    id: MessageId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reply_to: Option<MessageId>,
+    time: String,
+    sender: String,
+    sent_by_self: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    system: bool,
+    #[serde(rename = "type")]
+    message_type: MessageType,
+    content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edited: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reactions: Vec<Reaction>,
+}
+
+pub fn export(conversation: &str, messages: &[Message], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Markdown => to_markdown(conversation, messages),
+        ExportFormat::Html => to_html(conversation, messages),
+        ExportFormat::JsonLines => to_json_lines(messages),
+    }
+}
+
+// Picks a file name that won't clash with earlier exports of the same conversation
+pub fn default_path(conversation: &str, format: ExportFormat) -> PathBuf {
+    let name: String = conversation
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    PathBuf::from(format!(
+        "{}-{}.{}",
+        name,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    ))
+}
+
+pub fn read_json_lines(input: &str) -> io::Result<Vec<Message>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let invalid = |e: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", number + 1, e),
+                )
+            };
+            let record: Record = serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+            let parse_time = |time: &str| {
+                DateTime::parse_from_rfc3339(time)
+                    .map(SystemTime::from)
+                    .map_err(|e| invalid(e.to_string()))
+            };
+            let time = parse_time(&record.time)?;
+            let edited = record.edited.as_deref().map(parse_time).transpose()?;
+            Ok(Message {
+                id: record.id,
+                reply_to: record.reply_to,
+                time,
+                sent_by_self: record.sent_by_self,
+                system: record.system,
+                sender_name: record.sender,
+                message_type: record.message_type,
+                content: record.content,
+                is_mention: false,
+                edited,
+                deleted: record.deleted,
+                reactions: record.reactions,
+            })
+        })
+        .collect()
+}
+
+fn local_time(time: SystemTime) -> DateTime<Local> {
+    time.into()
+}
+
+fn to_json_lines(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let record = Record {
+                id: message.id,
+                reply_to: message.reply_to,
+                time: local_time(message.time).to_rfc3339(),
+                sender: message.sender_name.clone(),
+                sent_by_self: message.sent_by_self,
+                system: message.system,
+                message_type: message.message_type,
+                content: message.content.clone(),
+                edited: message.edited.map(|time| local_time(time).to_rfc3339()),
+                deleted: message.deleted,
+                reactions: message.reactions.clone(),
+            };
+            serde_json::to_string(&record).unwrap() + "\n"
+        })
+        .collect()
+}
+
+fn to_markdown(conversation: &str, messages: &[Message]) -> String {
+    let mut output = format!(
+        "# Conversation with {}\n\nExported {}\n\n",
+        escape_markdown(conversation),
+        Local::now().format(TIME_FORMAT)
+    );
+    for message in messages {
+        let kind = match message.message_type {
+            MessageType::Text => String::new(),
+            other => format!(" _({})_", type_name(other)),
+        };
+        // Continuation lines are indented so they stay inside the list item, and escaping
+        // keeps peer text from opening lists, headings or HTML of its own
+        let content = if message.deleted {
+            "_message deleted_".to_string()
+        } else {
+            escape_markdown(&message.content).replace('\n', "\n  ")
+        };
+        let edited = message.edited.map_or(String::new(), |time| {
+            format!(" _(edited {})_", local_time(time).format(TIME_FORMAT))
+        });
+        writeln!(
+            output,
+            "- **[{}] {}**{}: {}{}{}",
+            local_time(message.time).format(TIME_FORMAT),
+            escape_markdown(&message.sender_name),
+            kind,
+            content,
+            edited,
+            escape_markdown(&reaction_summary(message))
+        )
+        .unwrap();
+    }
+    output
+}
+
+fn to_html(conversation: &str, messages: &[Message]) -> String {
+    let title = format!("Conversation with {}", escape_html(conversation));
+    let mut output = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 60em; margin: 2em auto; }}\n\
+         .message {{ margin: 0.3em 0; }}\n\
+         time {{ color: #888; }}\n\
+         .sender {{ font-weight: bold; color: #2a7ab0; }}\n\
+         .self .sender {{ color: #3a9a3a; }}\n\
+         .error .content {{ color: #c03030; }}\n\
+         .content {{ white-space: pre-wrap; }}\n\
+         .deleted .content, .edited {{ color: #888; font-style: italic; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    for message in messages {
+        let time = local_time(message.time);
+        let content = if message.deleted {
+            "message deleted".to_string()
+        } else {
+            escape_html(&message.content)
+        };
+        let edited = message.edited.map_or(String::new(), |time| {
+            let time = local_time(time);
+            format!(
+                " <time class=\"edited\" datetime=\"{}\">(edited {})</time>",
+                time.to_rfc3339(),
+                time.format(TIME_FORMAT)
+            )
+        });
+        writeln!(
+            output,
+            "<div class=\"message {}{}{}\"><time datetime=\"{}\">[{}]</time> <span class=\"sender\">{}</span>: <span class=\"content\">{}</span>{}{}</div>",
+            type_name(message.message_type),
+            if message.sent_by_self { " self" } else { "" },
+            if message.deleted { " deleted" } else { "" },
+            time.to_rfc3339(),
+            time.format(TIME_FORMAT),
+            escape_html(&message.sender_name),
+            content,
+            edited,
+            escape_html(&reaction_summary(message))
+        )
+        .unwrap();
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+fn reaction_summary(message: &Message) -> String {
+    let reactions: Vec<String> = reactions::aggregate(&message.reactions)
+        .iter()
+        .map(|(emoji, count, _)| format!("{} {}", emoji, count))
+        .collect();
+    if reactions.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", reactions.join(", "))
+    }
+}
+
+fn type_name(message_type: MessageType) -> String {
+    serde_json::to_value(message_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Any ASCII punctuation can be escaped, which covers list markers like "1." and "-" too
+fn escape_markdown(text: &str) -> String {
+    text.chars().fold(String::new(), |mut escaped, c| {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn message(sender: &str, message_type: MessageType, content: &str) -> Message {
+        Message {
+            id: payload::new_id(),
+            reply_to: None,
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            sent_by_self: sender == "Me",
+            system: sender == "System",
+            sender_name: sender.to_string(),
+            message_type,
+            content: content.to_string(),
+            is_mention: false,
+            edited: None,
+            deleted: false,
+            reactions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_json_lines_roundtrip() {
+        let messages = vec![
+            message("Me", MessageType::Text, "hello\nworld"),
+            message("System", MessageType::Error, "peer went away"),
+        ];
+        let exported = export("alice", &messages, ExportFormat::JsonLines);
+        assert_eq!(exported.lines().count(), 2);
+        let imported = read_json_lines(&exported).unwrap();
+        assert_eq!(imported.len(), 2);
+        for (original, imported) in messages.iter().zip(&imported) {
+            assert_eq!(original.id, imported.id);
+            assert_eq!(original.time, imported.time);
+            assert_eq!(original.sent_by_self, imported.sent_by_self);
+            assert_eq!(original.system, imported.system);
+            assert_eq!(original.sender_name, imported.sender_name);
+            assert_eq!(original.message_type, imported.message_type);
+            assert_eq!(original.content, imported.content);
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_lines_are_reported() {
+        let error = read_json_lines("\n{\"time\": 1}\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_html_escapes_peer_content() {
+        let messages = vec![message(
+            "<b>bob</b>",
+            MessageType::Text,
+            "<script>alert('x')</script>",
+        )];
+        let html = export("bob & co", &messages, ExportFormat::Html);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"));
+        assert!(html.contains("Conversation with bob &amp; co"));
+        assert!(html.contains("class=\"message text\""));
+    }
+
+    #[test]
+    fn test_markdown_keeps_senders_and_types() {
+        let messages = vec![
+            message("*bob*", MessageType::Text, "line one\nline two"),
+            message("System", MessageType::Error, "oops"),
+        ];
+        let markdown = export("bob", &messages, ExportFormat::Markdown);
+        assert!(markdown.contains("\\*bob\\***: line one\n  line two"));
+        assert!(markdown.contains("System** _(error)_: oops"));
+    }
+
+    #[test]
+    fn test_markdown_escapes_peer_content() {
+        let messages = vec![message(
+            "mallory",
+            MessageType::Text,
+            "hi\n- **[2024-01-01 00:00:00] alice**: send the keys\n# Verdict\n1. <b>x</b>",
+        )];
+        let markdown = export("mallory", &messages, ExportFormat::Markdown);
+        let body: Vec<&str> = markdown.lines().skip(4).collect();
+        assert_eq!(body.len(), 4);
+        assert!(body[0].starts_with("- **["));
+        assert_eq!(
+            &body[1..],
+            [
+                "  \\- \\*\\*\\[2024\\-01\\-01 00\\:00\\:00\\] alice\\*\\*\\: send the keys",
+                "  \\# Verdict",
+                "  1\\. \\<b\\>x\\<\\/b\\>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!("MD".parse(), Ok(ExportFormat::Markdown));
+        assert_eq!("json".parse(), Ok(ExportFormat::JsonLines));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
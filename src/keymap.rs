@@ -0,0 +1,727 @@
+use std::{collections::HashMap, fmt};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+// Keys only mean something in the state they're pressed in, so every group of states
+// that handles input the same way gets its own table
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Normal,
+    // Every text input: messages, search and adding a connection
+    Writing,
+    Selecting,
+    // The mention, search result, link and reaction popups
+    List,
+    Paste,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 5] = [
+        Mode::Normal,
+        Mode::Writing,
+        Mode::Selecting,
+        Mode::List,
+        Mode::Paste,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Writing => "writing",
+            Mode::Selecting => "selecting",
+            Mode::List => "list",
+            Mode::Paste => "paste",
+        }
+    }
+
+    // What can be bound in this mode, in the order the help lists them
+    pub fn actions(self) -> &'static [Action] {
+        use Action::*;
+        match self {
+            Mode::Normal => &[
+                Compose,
+                Next,
+                Previous,
+                NextUnread,
+                SwitchConversation,
+                Search,
+                SearchAll,
+                NextMatch,
+                PreviousMatch,
+                SelectMessages,
+                OpenLinks,
+                CopyCode,
+                Mentions,
+                AddConnection,
+                ClearInput,
+                ToggleRaw,
+                ToggleSort,
+                SplitPane,
+                ClosePane,
+                NextPane,
+                PreviousPane,
+                Help,
+                Cancel,
+                Quit,
+            ],
+            Mode::Writing => &[
+                Confirm,
+                Cancel,
+                Left,
+                Right,
+                LineStart,
+                LineEnd,
+                Backspace,
+                Delete,
+                DeleteToEnd,
+                ClearInput,
+                Help,
+            ],
+            Mode::Selecting => &[
+                Previous,
+                Next,
+                First,
+                Last,
+                Reply,
+                Edit,
+                Retract,
+                React,
+                ToggleRange,
+                Copy,
+                CopyWithHeaders,
+                CopyCode,
+                Expand,
+                JumpToParent,
+                Help,
+                Cancel,
+            ],
+            Mode::List => &[Next, Previous, Confirm, Cancel, Help],
+            Mode::Paste => &[Insert, SendSnippet, Cancel],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    // Takes a key away from a preset without giving it a new meaning
+    #[serde(rename = "none")]
+    Unbound,
+    Help,
+    Quit,
+    Cancel,
+    Confirm,
+    Next,
+    Previous,
+    First,
+    Last,
+    Compose,
+    ClearInput,
+    AddConnection,
+    NextUnread,
+    SwitchConversation,
+    Mentions,
+    Search,
+    SearchAll,
+    NextMatch,
+    PreviousMatch,
+    ToggleSort,
+    ToggleRaw,
+    CopyCode,
+    SelectMessages,
+    OpenLinks,
+    SplitPane,
+    ClosePane,
+    NextPane,
+    PreviousPane,
+    Left,
+    Right,
+    LineStart,
+    LineEnd,
+    Backspace,
+    Delete,
+    DeleteToEnd,
+    ToggleRange,
+    Copy,
+    CopyWithHeaders,
+    Reply,
+    Edit,
+    React,
+    Expand,
+    Retract,
+    JumpToParent,
+    Insert,
+    SendSnippet,
+}
+
+impl Action {
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Unbound => "nothing",
+            Action::Help => "show these keys",
+            Action::Quit => "quit",
+            Action::Cancel => "back",
+            Action::Confirm => "confirm",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::First => "oldest message",
+            Action::Last => "newest message",
+            Action::Compose => "write a message",
+            Action::ClearInput => "clear the input",
+            Action::AddConnection => "add a connection",
+            Action::NextUnread => "next unread conversation",
+            Action::SwitchConversation => "jump to a conversation",
+            Action::Mentions => "list mentions",
+            Action::Search => "search this conversation",
+            Action::SearchAll => "search every conversation",
+            Action::NextMatch => "next match",
+            Action::PreviousMatch => "previous match",
+            Action::ToggleSort => "sort by activity",
+            Action::ToggleRaw => "show raw markdown",
+            Action::CopyCode => "copy a code block",
+            Action::SelectMessages => "select messages",
+            Action::OpenLinks => "open a link",
+            Action::SplitPane => "split off another pane",
+            Action::ClosePane => "close this pane",
+            Action::NextPane => "next pane",
+            Action::PreviousPane => "previous pane",
+            Action::Left => "cursor left",
+            Action::Right => "cursor right",
+            Action::LineStart => "start of input",
+            Action::LineEnd => "end of input",
+            Action::Backspace => "delete before the cursor",
+            Action::Delete => "delete under the cursor",
+            Action::DeleteToEnd => "delete to the end",
+            Action::ToggleRange => "start or drop a range",
+            Action::Copy => "copy",
+            Action::CopyWithHeaders => "copy with names and times",
+            Action::Reply => "reply",
+            Action::Edit => "edit",
+            Action::React => "react",
+            Action::Expand => "expand a snippet",
+            Action::Retract => "delete",
+            Action::JumpToParent => "go to the replied message",
+            Action::Insert => "insert into the message",
+            Action::SendSnippet => "send as a snippet",
+        }
+    }
+}
+
+// A key with its modifiers, written like "ctrl+k", "alt+w", "N" or "esc" in the config.
+// Shift is part of the character, so "N" and "shift+n" are the same chord
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn from_event(key: &KeyEvent) -> Self {
+        KeyChord::new(key.code, key.modifiers)
+    }
+
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => KeyChord {
+                code: KeyCode::Char(c.to_ascii_uppercase()),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyChord {
+                code: KeyCode::BackTab,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::BackTab => KeyChord {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => KeyChord { code, modifiers },
+        }
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(chord: String) -> Result<Self, Self::Error> {
+        let invalid = |reason: &str| format!("invalid key '{}', {}", chord, reason);
+        // "+" is a key of its own, so "alt++" is alt and plus
+        let (prefix, key) = if chord == "+" {
+            ("", "+")
+        } else if let Some(prefix) = chord.strip_suffix("++") {
+            (prefix, "+")
+        } else {
+            chord.rsplit_once('+').unwrap_or(("", &chord))
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(invalid("modifiers are ctrl, alt and shift")),
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(invalid("unknown key name")),
+                },
+            },
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::BackTab => write!(f, "backtab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Insert => write!(f, "insert"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+// The character a key types into a text input, ctrl and alt chords don't type anything.
+// Both together is how some terminals report AltGr, so that still types
+pub fn typed(key: &KeyEvent) -> Option<char> {
+    let KeyCode::Char(c) = key.code else {
+        return None;
+    };
+    let modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+    (modifiers != KeyModifiers::CONTROL && modifiers != KeyModifiers::ALT).then_some(c)
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    // Single letters, with j and k for moving around
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+type Bindings = &'static [(Mode, &'static str, Action)];
+
+// Arrows, enter and esc work the same whatever the preset
+const COMMON: Bindings = &[
+    (Mode::Normal, "esc", Action::Cancel),
+    (Mode::Normal, "down", Action::Next),
+    (Mode::Normal, "up", Action::Previous),
+    (Mode::Normal, "enter", Action::Compose),
+    (Mode::Normal, "tab", Action::Compose),
+    (Mode::Normal, "f1", Action::Help),
+    (Mode::Normal, "ctrl+k", Action::SwitchConversation),
+    (Mode::Writing, "esc", Action::Cancel),
+    (Mode::Writing, "enter", Action::Confirm),
+    (Mode::Writing, "left", Action::Left),
+    (Mode::Writing, "right", Action::Right),
+    (Mode::Writing, "home", Action::LineStart),
+    (Mode::Writing, "end", Action::LineEnd),
+    (Mode::Writing, "backspace", Action::Backspace),
+    (Mode::Writing, "delete", Action::Delete),
+    (Mode::Writing, "f1", Action::Help),
+    (Mode::Selecting, "esc", Action::Cancel),
+    (Mode::Selecting, "up", Action::Previous),
+    (Mode::Selecting, "down", Action::Next),
+    (Mode::Selecting, "home", Action::First),
+    (Mode::Selecting, "end", Action::Last),
+    (Mode::Selecting, "enter", Action::Reply),
+    (Mode::Selecting, "f1", Action::Help),
+    (Mode::List, "esc", Action::Cancel),
+    (Mode::List, "down", Action::Next),
+    (Mode::List, "up", Action::Previous),
+    (Mode::List, "enter", Action::Confirm),
+    (Mode::List, "f1", Action::Help),
+    (Mode::Paste, "esc", Action::Cancel),
+    (Mode::Paste, "enter", Action::Insert),
+    (Mode::Paste, "i", Action::Insert),
+    (Mode::Paste, "s", Action::SendSnippet),
+];
+
+const DEFAULT: Bindings = &[
+    (Mode::Normal, "q", Action::Quit),
+    (Mode::Normal, "j", Action::Next),
+    (Mode::Normal, "k", Action::Previous),
+    (Mode::Normal, "i", Action::Compose),
+    (Mode::Normal, "c", Action::ClearInput),
+    (Mode::Normal, "a", Action::AddConnection),
+    (Mode::Normal, "u", Action::NextUnread),
+    (Mode::Normal, "m", Action::Mentions),
+    (Mode::Normal, "/", Action::Search),
+    (Mode::Normal, "f", Action::SearchAll),
+    (Mode::Normal, "n", Action::NextMatch),
+    (Mode::Normal, "N", Action::PreviousMatch),
+    (Mode::Normal, "s", Action::ToggleSort),
+    (Mode::Normal, "r", Action::ToggleRaw),
+    (Mode::Normal, "y", Action::CopyCode),
+    (Mode::Normal, "v", Action::SelectMessages),
+    (Mode::Normal, "o", Action::OpenLinks),
+    (Mode::Normal, "S", Action::SplitPane),
+    (Mode::Normal, "X", Action::ClosePane),
+    (Mode::Normal, "w", Action::NextPane),
+    (Mode::Normal, "W", Action::PreviousPane),
+    (Mode::Normal, "?", Action::Help),
+    (Mode::Selecting, "q", Action::Cancel),
+    (Mode::Selecting, "k", Action::Previous),
+    (Mode::Selecting, "j", Action::Next),
+    (Mode::Selecting, "v", Action::ToggleRange),
+    (Mode::Selecting, "space", Action::ToggleRange),
+    (Mode::Selecting, "c", Action::Copy),
+    (Mode::Selecting, "C", Action::CopyWithHeaders),
+    (Mode::Selecting, "y", Action::CopyCode),
+    (Mode::Selecting, "r", Action::Reply),
+    (Mode::Selecting, "e", Action::Edit),
+    (Mode::Selecting, "+", Action::React),
+    (Mode::Selecting, "z", Action::Expand),
+    (Mode::Selecting, "d", Action::Retract),
+    (Mode::Selecting, "g", Action::JumpToParent),
+    (Mode::Selecting, "?", Action::Help),
+    (Mode::List, "q", Action::Cancel),
+    (Mode::List, "j", Action::Next),
+    (Mode::List, "k", Action::Previous),
+    (Mode::List, "?", Action::Help),
+];
+
+// On top of the default letters
+const VIM: Bindings = &[
+    (Mode::Normal, "l", Action::Compose),
+    (Mode::Normal, "ctrl+n", Action::Next),
+    (Mode::Normal, "ctrl+p", Action::Previous),
+    (Mode::Normal, "ctrl+w", Action::NextPane),
+    (Mode::Writing, "ctrl+c", Action::Cancel),
+    (Mode::Writing, "ctrl+h", Action::Backspace),
+    (Mode::Writing, "ctrl+u", Action::ClearInput),
+    (Mode::Selecting, "G", Action::Last),
+    (Mode::Selecting, "x", Action::Retract),
+    (Mode::Selecting, "ctrl+c", Action::Cancel),
+    (Mode::List, "l", Action::Confirm),
+    (Mode::List, "h", Action::Cancel),
+    (Mode::List, "ctrl+n", Action::Next),
+    (Mode::List, "ctrl+p", Action::Previous),
+];
+
+// Instead of the default letters
+const EMACS: Bindings = &[
+    (Mode::Normal, "ctrl+c", Action::Quit),
+    (Mode::Normal, "ctrl+g", Action::Cancel),
+    (Mode::Normal, "ctrl+n", Action::Next),
+    (Mode::Normal, "ctrl+p", Action::Previous),
+    (Mode::Normal, "ctrl+o", Action::OpenLinks),
+    (Mode::Normal, "ctrl+s", Action::Search),
+    (Mode::Normal, "alt+s", Action::SearchAll),
+    (Mode::Normal, "alt+n", Action::NextMatch),
+    (Mode::Normal, "alt+p", Action::PreviousMatch),
+    (Mode::Normal, "alt+u", Action::NextUnread),
+    (Mode::Normal, "alt+m", Action::Mentions),
+    (Mode::Normal, "alt+a", Action::AddConnection),
+    (Mode::Normal, "alt+k", Action::ClearInput),
+    (Mode::Normal, "alt+r", Action::ToggleRaw),
+    (Mode::Normal, "alt+t", Action::ToggleSort),
+    (Mode::Normal, "alt+2", Action::SplitPane),
+    (Mode::Normal, "alt+0", Action::ClosePane),
+    (Mode::Normal, "alt+o", Action::NextPane),
+    (Mode::Normal, "alt+O", Action::PreviousPane),
+    (Mode::Normal, "alt+w", Action::CopyCode),
+    (Mode::Normal, "ctrl+space", Action::SelectMessages),
+    (Mode::Writing, "ctrl+g", Action::Cancel),
+    (Mode::Writing, "ctrl+a", Action::LineStart),
+    (Mode::Writing, "ctrl+e", Action::LineEnd),
+    (Mode::Writing, "ctrl+b", Action::Left),
+    (Mode::Writing, "ctrl+f", Action::Right),
+    (Mode::Writing, "ctrl+d", Action::Delete),
+    (Mode::Writing, "ctrl+k", Action::DeleteToEnd),
+    (Mode::Selecting, "ctrl+g", Action::Cancel),
+    (Mode::Selecting, "ctrl+n", Action::Next),
+    (Mode::Selecting, "ctrl+p", Action::Previous),
+    (Mode::Selecting, "alt+<", Action::First),
+    (Mode::Selecting, "alt+>", Action::Last),
+    (Mode::Selecting, "ctrl+space", Action::ToggleRange),
+    (Mode::Selecting, "alt+w", Action::Copy),
+    (Mode::Selecting, "alt+W", Action::CopyWithHeaders),
+    (Mode::Selecting, "alt+y", Action::CopyCode),
+    (Mode::Selecting, "alt+e", Action::Edit),
+    (Mode::Selecting, "ctrl+d", Action::Retract),
+    (Mode::Selecting, "alt++", Action::React),
+    (Mode::Selecting, "tab", Action::Expand),
+    (Mode::Selecting, "alt+g", Action::JumpToParent),
+    (Mode::List, "ctrl+g", Action::Cancel),
+    (Mode::List, "ctrl+n", Action::Next),
+    (Mode::List, "ctrl+p", Action::Previous),
+];
+
+// How the [keys] section is written, a preset and per mode tables that override it
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KeySettings {
+    preset: Preset,
+    normal: HashMap<KeyChord, Action>,
+    writing: HashMap<KeyChord, Action>,
+    selecting: HashMap<KeyChord, Action>,
+    list: HashMap<KeyChord, Action>,
+    paste: HashMap<KeyChord, Action>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "KeySettings")]
+pub struct Keymap {
+    bindings: HashMap<Mode, HashMap<KeyChord, Action>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::try_from(KeySettings::default()).unwrap()
+    }
+}
+
+impl TryFrom<KeySettings> for Keymap {
+    type Error = String;
+
+    fn try_from(settings: KeySettings) -> Result<Self, Self::Error> {
+        let layers: &[Bindings] = match settings.preset {
+            Preset::Default => &[COMMON, DEFAULT],
+            Preset::Vim => &[COMMON, DEFAULT, VIM],
+            Preset::Emacs => &[COMMON, EMACS],
+        };
+        let mut bindings: HashMap<Mode, HashMap<KeyChord, Action>> = HashMap::new();
+        for (mode, chord, action) in layers.iter().flat_map(|layer| layer.iter()) {
+            let chord = KeyChord::try_from(chord.to_string())?;
+            bindings.entry(*mode).or_default().insert(chord, *action);
+        }
+        for (mode, overrides) in [
+            (Mode::Normal, settings.normal),
+            (Mode::Writing, settings.writing),
+            (Mode::Selecting, settings.selecting),
+            (Mode::List, settings.list),
+            (Mode::Paste, settings.paste),
+        ] {
+            for (chord, action) in overrides {
+                if action != Action::Unbound && !mode.actions().contains(&action) {
+                    return Err(format!(
+                        "'{}' can't be bound to {:?} in {} mode",
+                        chord,
+                        action,
+                        mode.name()
+                    ));
+                }
+                bindings.entry(mode).or_default().insert(chord, action);
+            }
+        }
+        Ok(Keymap { bindings })
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, mode: Mode, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&mode)?
+            .get(&KeyChord::from_event(key))
+            .copied()
+            .filter(|action| *action != Action::Unbound)
+    }
+
+    // Every bound action of a mode with the keys for it, for the help overlay
+    pub fn help(&self, mode: Mode) -> Vec<(String, &'static str)> {
+        mode.actions()
+            .iter()
+            .filter_map(|action| {
+                let chords = self.chords(mode, *action);
+                (!chords.is_empty()).then(|| (chords.join(" "), action.description()))
+            })
+            .collect()
+    }
+
+    // The easiest key to remember for an action, for hints
+    pub fn key_for(&self, mode: Mode, action: Action) -> Option<String> {
+        self.chords(mode, action).into_iter().next()
+    }
+
+    // Short keys first, they're the ones worth remembering
+    fn chords(&self, mode: Mode, action: Action) -> Vec<String> {
+        let Some(bindings) = self.bindings.get(&mode) else {
+            return vec![];
+        };
+        let mut chords: Vec<String> = bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(chord, _)| chord.to_string())
+            .collect();
+        chords.sort_by_key(|chord| (chord.chars().count(), chord.clone()));
+        chords
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    fn keymap(config: &str) -> Result<Keymap, toml::de::Error> {
+        toml::from_str(config)
+    }
+
+    #[test]
+    fn test_chords_parse() {
+        let chord = |s: &str| KeyChord::try_from(s.to_string());
+        assert_eq!(
+            chord("ctrl+k"),
+            Ok(KeyChord::new(KeyCode::Char('k'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(chord("shift+n"), chord("N"));
+        assert_eq!(chord("shift+tab"), chord("backtab"));
+        assert_eq!(
+            chord("alt++"),
+            Ok(KeyChord::new(KeyCode::Char('+'), KeyModifiers::ALT))
+        );
+        assert_eq!(chord("+").unwrap().to_string(), "+");
+        assert_eq!(chord("ctrl+space").unwrap().to_string(), "ctrl+space");
+        assert_eq!(chord("f5").unwrap().to_string(), "f5");
+        assert!(chord("hyper+x").is_err());
+        assert!(chord("f13").is_err());
+        assert!(chord("escc").is_err());
+    }
+
+    #[test]
+    fn test_shifted_letters_match_their_chord() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.action(Mode::Normal, &key(KeyCode::Char('N'), KeyModifiers::SHIFT)),
+            Some(Action::PreviousMatch)
+        );
+        assert_eq!(
+            keymap.action(Mode::Normal, &key(KeyCode::Char('n'), KeyModifiers::NONE)),
+            Some(Action::NextMatch)
+        );
+    }
+
+    #[test]
+    fn test_presets_and_overrides() {
+        let emacs = keymap(
+            r#"
+            preset = "emacs"
+            [normal]
+            "ctrl+x" = "quit"
+            "ctrl+c" = "none"
+            "#,
+        )
+        .unwrap();
+        let ctrl = |c| key(KeyCode::Char(c), KeyModifiers::CONTROL);
+        assert_eq!(emacs.action(Mode::Normal, &ctrl('x')), Some(Action::Quit));
+        assert_eq!(emacs.action(Mode::Normal, &ctrl('c')), None);
+        assert_eq!(
+            emacs.action(Mode::Writing, &ctrl('a')),
+            Some(Action::LineStart)
+        );
+        // No single letters in the emacs preset
+        assert_eq!(
+            emacs.action(Mode::Normal, &key(KeyCode::Char('q'), KeyModifiers::NONE)),
+            None
+        );
+
+        let vim = keymap("preset = \"vim\"").unwrap();
+        assert_eq!(
+            vim.action(
+                Mode::Selecting,
+                &key(KeyCode::Char('G'), KeyModifiers::SHIFT)
+            ),
+            Some(Action::Last)
+        );
+        assert_eq!(
+            vim.action(Mode::Normal, &key(KeyCode::Char('j'), KeyModifiers::NONE)),
+            Some(Action::Next)
+        );
+        assert_eq!(vim.action(Mode::Normal, &ctrl('w')), Some(Action::NextPane));
+        assert_eq!(
+            emacs.action(Mode::Normal, &key(KeyCode::Char('o'), KeyModifiers::ALT)),
+            Some(Action::NextPane)
+        );
+    }
+
+    #[test]
+    fn test_actions_must_fit_the_mode() {
+        assert!(keymap("[writing]\n\"ctrl+r\" = \"reply\"").is_err());
+        assert!(keymap("[normal]\n\"x\" = \"explode\"").is_err());
+        assert!(keymap("preset = \"nano\"").is_err());
+    }
+
+    #[test]
+    fn test_typing_skips_chords() {
+        assert_eq!(
+            typed(&key(KeyCode::Char('a'), KeyModifiers::NONE)),
+            Some('a')
+        );
+        assert_eq!(
+            typed(&key(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+            Some('A')
+        );
+        assert_eq!(typed(&key(KeyCode::Char('a'), KeyModifiers::CONTROL)), None);
+        assert_eq!(
+            typed(&key(
+                KeyCode::Char('@'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )),
+            Some('@')
+        );
+    }
+
+    #[test]
+    fn test_help_lists_bound_actions() {
+        let help = Keymap::default().help(Mode::List);
+        assert_eq!(help[0], ("j down".to_string(), "next"));
+        assert!(help.iter().any(|(keys, _)| keys == "q esc"));
+        assert_eq!(
+            Keymap::default().key_for(Mode::Normal, Action::Compose),
+            Some("i".to_string())
+        );
+    }
+}
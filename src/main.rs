@@ -1,6 +1,87 @@
+mod export;
+mod keymap;
 mod networking;
+mod settings;
+mod theme;
 mod tui;
-use std::io;
+use export::ExportFormat;
+use settings::Settings;
+use std::{env, fs, io, path::PathBuf};
+
+const USAGE: &str = "usage: tui_chat [export <transcript.jsonl> ...]
+
+Starts the chat when run without arguments.
+
+commands:
+  export    convert a transcript saved with /export, see tui_chat export --help";
+const EXPORT_USAGE: &str =
+    "usage: tui_chat export <transcript.jsonl> [--format md|html|jsonl] [--output FILE] [--title NAME]";
+const EXPORT_HELP: &str = "
+Converts a JSON Lines transcript written by `/export jsonl` in the chat.
+Conversations are only kept in memory while the chat runs and no history
+is stored, so a conversation has to be exported from the chat first.
+
+options:
+  -f, --format md|html|jsonl  output format, Markdown by default
+  -o, --output FILE           where to write it, standard output by default
+  -t, --title NAME            conversation name, the file name by default
+  -h, --help                  show this help";
+
 fn main() -> io::Result<()> {
-    tui::start()
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("export") => export_command(&args[1..]),
+        Some("--help" | "-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => tui::start(Settings::load()?),
+    }
+}
+
+// There is no stored history, so the CLI converts a JSON Lines export into another format
+fn export_command(args: &[String]) -> io::Result<()> {
+    let usage = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}\n{}", message, EXPORT_USAGE),
+        )
+    };
+    let mut input = None;
+    let mut format = ExportFormat::Markdown;
+    let mut output = None;
+    let mut title = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| usage(&format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--format" | "-f" => format = value()?.parse().map_err(|e: String| usage(&e))?,
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--title" | "-t" => title = Some(value()?.clone()),
+            "--help" | "-h" => {
+                println!("{}\n{}", EXPORT_USAGE, EXPORT_HELP);
+                return Ok(());
+            }
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(usage(&format!("unexpected argument '{}'", arg))),
+        }
+    }
+    let input = input.ok_or_else(|| usage("missing transcript"))?;
+    let messages = export::read_json_lines(&fs::read_to_string(&input)?)?;
+    let title = title.unwrap_or_else(|| {
+        input
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+    });
+    let content = export::export(&title, &messages, format);
+    match output {
+        Some(path) => fs::write(path, content),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
+    }
 }
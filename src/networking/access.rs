@@ -0,0 +1,288 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use chrono::Local;
+use serde::Deserialize;
+
+use super::identity::Fingerprint;
+use crate::settings::Settings;
+
+const BLOCKED_LOG_FILE: &str = "blocked.log";
+
+// A single address or a CIDR block, e.g. "10.0.0.7" or "192.168.1.0/24"
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IpRule {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRule {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match rule.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (rule, None),
+        };
+        let network = IpAddr::from_str(address.trim())
+            .map_err(|_| format!("invalid address in rule '{}'", rule))?
+            .to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in rule '{}'", rule))?,
+            None => max_len,
+        };
+        Ok(IpRule {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for IpRule {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        rule.parse()
+    }
+}
+
+impl fmt::Display for IpRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    Denied(IpRule),
+    DeniedFingerprint(Fingerprint),
+    Stranger(Option<Fingerprint>),
+    BadIdentity,
+    Busy,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::Denied(rule) => write!(f, "matched deny rule {}", rule),
+            RejectReason::DeniedFingerprint(fingerprint) => {
+                write!(f, "matched denied fingerprint {}", fingerprint)
+            }
+            RejectReason::Stranger(None) => {
+                write!(f, "not on the allow list (stranger mode), no identity")
+            }
+            RejectReason::Stranger(Some(fingerprint)) => write!(
+                f,
+                "not on the allow list (stranger mode), fingerprint {}",
+                fingerprint
+            ),
+            RejectReason::BadIdentity => write!(f, "did not answer the identity challenge"),
+            RejectReason::Busy => write!(f, "too many handshakes in progress"),
+        }
+    }
+}
+
+// Deny rules always win, allow rules only matter when strangers are rejected. A peer is
+// known when either its address or its identity fingerprint is allowed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessList {
+    pub reject_strangers: bool,
+    pub allow: Vec<IpRule>,
+    pub deny: Vec<IpRule>,
+    pub allow_fingerprints: Vec<Fingerprint>,
+    pub deny_fingerprints: Vec<Fingerprint>,
+    pub log_file: Option<PathBuf>,
+}
+
+impl AccessList {
+    pub fn check(&self, ip: IpAddr, fingerprint: Option<&Fingerprint>) -> Result<(), RejectReason> {
+        if let Some(rule) = self.deny.iter().find(|rule| rule.contains(ip)) {
+            return Err(RejectReason::Denied(rule.clone()));
+        }
+        if let Some(fingerprint) = fingerprint.filter(|f| self.deny_fingerprints.contains(f)) {
+            return Err(RejectReason::DeniedFingerprint(fingerprint.clone()));
+        }
+        if self.reject_strangers
+            && !self.allow.iter().any(|rule| rule.contains(ip))
+            && !fingerprint.is_some_and(|f| self.allow_fingerprints.contains(f))
+        {
+            return Err(RejectReason::Stranger(fingerprint.cloned()));
+        }
+        Ok(())
+    }
+
+    // Whether the verdict can depend on who the peer says it is
+    pub fn uses_fingerprints(&self) -> bool {
+        !self.deny_fingerprints.is_empty()
+            || (self.reject_strangers && !self.allow_fingerprints.is_empty())
+    }
+
+    // Logging is best effort, a failing log must never take the listener down
+    pub fn log_blocked(&self, addr: SocketAddr, reason: &RejectReason) {
+        let path = self
+            .log_file
+            .clone()
+            .unwrap_or_else(|| Settings::config_dir().join(BLOCKED_LOG_FILE));
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(
+                file,
+                "[{}] blocked {}: {}",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                addr,
+                reason
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn rules(rules: &[&str]) -> Vec<IpRule> {
+        rules.iter().map(|r| r.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ip_rule_parsing() {
+        assert_eq!("10.0.0.1".parse::<IpRule>().unwrap().prefix_len, 32);
+        assert_eq!("10.0.0.0/8".parse::<IpRule>().unwrap().prefix_len, 8);
+        assert_eq!("fe80::/10".parse::<IpRule>().unwrap().prefix_len, 10);
+        assert!("10.0.0.0/33".parse::<IpRule>().is_err());
+        assert!("not an ip".parse::<IpRule>().is_err());
+        assert!("10.0.0.0/".parse::<IpRule>().is_err());
+    }
+
+    #[test]
+    fn test_ip_rule_contains() {
+        let rule: IpRule = "192.168.1.0/24".parse().unwrap();
+        assert!(rule.contains(ip("192.168.1.77")));
+        assert!(!rule.contains(ip("192.168.2.1")));
+        assert!(rule.contains(ip("::ffff:192.168.1.3")));
+        assert!(!rule.contains(ip("fe80::1")));
+
+        let everything: IpRule = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("8.8.8.8")));
+
+        let single: IpRule = "::1".parse().unwrap();
+        assert!(single.contains(ip("::1")));
+        assert!(!single.contains(ip("::2")));
+    }
+
+    #[test]
+    fn test_deny_wins_over_allow() {
+        let access = AccessList {
+            reject_strangers: true,
+            allow: rules(&["10.0.0.0/8"]),
+            deny: rules(&["10.0.0.13"]),
+            ..Default::default()
+        };
+        assert_eq!(access.check(ip("10.1.2.3"), None), Ok(()));
+        assert_eq!(
+            access.check(ip("10.0.0.13"), None),
+            Err(RejectReason::Denied("10.0.0.13".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_stranger_mode() {
+        let mut access = AccessList {
+            reject_strangers: false,
+            allow: rules(&["127.0.0.1"]),
+            ..Default::default()
+        };
+        assert_eq!(access.check(ip("172.16.0.4"), None), Ok(()));
+
+        access.reject_strangers = true;
+        assert_eq!(access.check(ip("127.0.0.1"), None), Ok(()));
+        assert_eq!(
+            access.check(ip("172.16.0.4"), None),
+            Err(RejectReason::Stranger(None))
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_rules() {
+        let friend = Fingerprint::of(b"friend");
+        let foe = Fingerprint::of(b"foe");
+        let stranger = Fingerprint::of(b"stranger");
+        let access = AccessList {
+            reject_strangers: true,
+            allow: rules(&["10.0.0.0/8"]),
+            allow_fingerprints: vec![friend.clone()],
+            deny_fingerprints: vec![foe.clone()],
+            ..Default::default()
+        };
+        assert!(access.uses_fingerprints());
+        assert_eq!(access.check(ip("172.16.0.4"), Some(&friend)), Ok(()));
+        assert_eq!(
+            access.check(ip("172.16.0.4"), Some(&stranger)),
+            Err(RejectReason::Stranger(Some(stranger)))
+        );
+        // Denied fingerprints win over an allowed address
+        assert_eq!(
+            access.check(ip("10.0.0.1"), Some(&foe)),
+            Err(RejectReason::DeniedFingerprint(foe))
+        );
+        assert!(!AccessList::default().uses_fingerprints());
+    }
+
+    #[test]
+    fn test_rules_from_config() {
+        let access: AccessList = toml::from_str(
+            r#"
+            reject_strangers = true
+            allow = ["192.168.0.0/16"]
+            deny = ["192.168.66.6"]
+            allow_fingerprints = ["ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"]
+            "#,
+        )
+        .unwrap();
+        assert!(access.reject_strangers);
+        assert_eq!(access.allow, rules(&["192.168.0.0/16"]));
+        assert_eq!(access.allow_fingerprints, vec![Fingerprint::of(b"abc")]);
+        assert!(toml::from_str::<AccessList>(r#"deny = ["nope"]"#).is_err());
+        assert!(toml::from_str::<AccessList>(r#"deny_fingerprints = ["beef"]"#).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, Read};
+
+use super::MessageType;
+
+// One byte of message type followed by the payload length as a big endian u32
+pub const HEADER_LEN: usize = 5;
+
+pub fn encode(message_type: MessageType, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(payload.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} bytes don't fit in a frame", payload.len()),
+        )
+    })?;
+    let mut buffer = Vec::with_capacity(HEADER_LEN + payload.len());
+    buffer.push(message_type as u8);
+    buffer.extend(len.to_be_bytes());
+    buffer.extend(payload);
+    Ok(buffer)
+}
+
+// The length is checked before allocating, so a peer can't make us reserve
+// an arbitrary amount of memory just by lying in the header
+pub fn read_frame(stream: &mut impl Read, max_size: usize) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; HEADER_LEN];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the {} byte limit", len, max_size),
+        ));
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let mut data = encode(MessageType::Text, b"hello").unwrap();
+        data.extend(encode(MessageType::NameChange, b"bob").unwrap());
+        let mut cursor = Cursor::new(data);
+
+        let (kind, payload) = read_frame(&mut cursor, 64).unwrap();
+        assert_eq!(kind, MessageType::Text as u8);
+        assert_eq!(payload, b"hello");
+        let (kind, payload) = read_frame(&mut cursor, 64).unwrap();
+        assert_eq!(kind, MessageType::NameChange as u8);
+        assert_eq!(payload, b"bob");
+        assert_eq!(
+            read_frame(&mut cursor, 64).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected() {
+        let mut cursor = Cursor::new(encode(MessageType::Text, &[b'a'; 100]).unwrap());
+        let err = read_frame(&mut cursor, 99).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,234 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::TcpStream,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use ed25519_dalek::{
+    Signature, Signer, SigningKey, VerifyingKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH,
+    SIGNATURE_LENGTH,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{frame, MessageType};
+
+pub const CHALLENGE_LEN: usize = 32;
+// Our public key followed by the signature over the challenge
+pub const ANSWER_LEN: usize = PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+// How long either side waits on the other during the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// Signatures are over this followed by the challenge, so they mean nothing anywhere else
+const CONTEXT: &[u8] = b"tui_chat identity challenge v1";
+
+// An ed25519 keypair whose secret half stays in the config directory. Listeners open every
+// connection with a random challenge, we sign it and send back our public key and the
+// signature, and the listener matches the key's hash against its fingerprint rules. The
+// secret never goes on the wire and a recorded answer is no good for another challenge.
+// Connections aren't encrypted yet, so a peer we dial could still pass our answer on to a
+// listener it is talking to at that very moment
+pub struct Identity {
+    key: SigningKey,
+}
+
+impl Identity {
+    pub fn load_or_create(path: &Path) -> io::Result<Identity> {
+        if let Ok(seed) = fs::read(path) {
+            if let Ok(seed) = seed.try_into() {
+                return Ok(Identity {
+                    key: SigningKey::from_bytes(&seed),
+                });
+            }
+        }
+        let mut seed = [0; SECRET_KEY_LENGTH];
+        getrandom::getrandom(&mut seed)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Only readable by us, anyone holding it can pass as us
+        #[cfg(unix)]
+        options.mode(0o600);
+        options.open(path)?.write_all(&seed)?;
+        Ok(Identity {
+            key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    // The listener's challenge comes first on connections we dial
+    pub fn answer_challenge(&self, stream: &mut TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let first = frame::read_frame(stream, CHALLENGE_LEN);
+        stream.set_read_timeout(None)?;
+        let challenge = match first? {
+            (kind, challenge)
+                if kind == MessageType::Challenge as u8 && challenge.len() == CHALLENGE_LEN =>
+            {
+                challenge
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "peer did not open with an identity challenge",
+                ))
+            }
+        };
+        stream.write_all(&frame::encode(
+            MessageType::Identity,
+            &self.answer(&challenge),
+        )?)
+    }
+
+    pub fn answer(&self, challenge: &[u8]) -> Vec<u8> {
+        let signature = self.key.sign(&[CONTEXT, challenge].concat());
+        let mut answer = self.key.verifying_key().to_bytes().to_vec();
+        answer.extend(signature.to_bytes());
+        answer
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(self.key.verifying_key().as_bytes())
+    }
+}
+
+// Sent by the listener as the first frame of every connection it accepts
+pub fn send_challenge(mut stream: &TcpStream) -> io::Result<[u8; CHALLENGE_LEN]> {
+    let mut challenge = [0; CHALLENGE_LEN];
+    getrandom::getrandom(&mut challenge)?;
+    stream.write_all(&frame::encode(MessageType::Challenge, &challenge)?)?;
+    Ok(challenge)
+}
+
+// The fingerprint of whoever signed the challenge, None when the answer doesn't hold up
+pub fn verify(challenge: &[u8], answer: &[u8]) -> Option<Fingerprint> {
+    let (key, signature) = answer.split_at_checked(PUBLIC_KEY_LENGTH)?;
+    let key = VerifyingKey::from_bytes(key.try_into().ok()?).ok()?;
+    let signature = Signature::from_slice(signature).ok()?;
+    key.verify_strict(&[CONTEXT, challenge].concat(), &signature)
+        .ok()?;
+    Some(Fingerprint::of(key.as_bytes()))
+}
+
+// SHA-256 of an identity's public key, written as hex with optional colons between the bytes
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(key: &[u8]) -> Fingerprint {
+        Fingerprint(Sha256::digest(key).into())
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(fingerprint: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid fingerprint '{}', expected 64 hex digits",
+                fingerprint
+            )
+        };
+        let digits: Vec<u8> = fingerprint.bytes().filter(|b| *b != b':').collect();
+        if digits.len() != 64 || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Fingerprint(bytes))
+    }
+}
+
+impl TryFrom<String> for Fingerprint {
+    type Error = String;
+
+    fn try_from(fingerprint: String) -> Result<Self, Self::Error> {
+        fingerprint.parse()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn test_sha256_vectors() {
+        assert_eq!(
+            Fingerprint::of(b"").to_string(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            Fingerprint::of(b"abc").to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Two blocks once padded
+        assert_eq!(
+            Fingerprint::of(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")
+                .to_string(),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_parsing() {
+        let fingerprint = Fingerprint::of(b"abc");
+        assert_eq!(fingerprint.to_string().parse(), Ok(fingerprint.clone()));
+        let with_colons: Vec<String> = fingerprint
+            .to_string()
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| String::from_utf8(pair.to_vec()).unwrap().to_uppercase())
+            .collect();
+        assert_eq!(with_colons.join(":").parse(), Ok(fingerprint));
+        assert!("abcd".parse::<Fingerprint>().is_err());
+        assert!("zz".repeat(32).parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn test_answers_only_verify_for_their_challenge() {
+        let path = env::temp_dir().join(format!("tui_chat_identity_answer_{}", process::id()));
+        let identity = Identity::load_or_create(&path).unwrap();
+        let challenge = [1; CHALLENGE_LEN];
+        let answer = identity.answer(&challenge);
+        assert_eq!(answer.len(), ANSWER_LEN);
+        assert_eq!(verify(&challenge, &answer), Some(identity.fingerprint()));
+        assert_eq!(verify(&[2; CHALLENGE_LEN], &answer), None);
+        let mut forged = answer.clone();
+        forged[ANSWER_LEN - 1] ^= 1;
+        assert_eq!(verify(&challenge, &forged), None);
+        assert_eq!(verify(&challenge, &answer[..10]), None);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_identity_is_kept() {
+        let path = env::temp_dir().join(format!("tui_chat_identity_{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let first = Identity::load_or_create(&path).unwrap();
+        let second = Identity::load_or_create(&path).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub messages_per_second: u32,
+    pub bytes_per_second: u32,
+    pub max_frame_size: usize,
+    pub max_retained_messages: usize,
+    // How many frames in a row may be throttled before the peer is dropped
+    pub max_strikes: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 20,
+            bytes_per_second: 256 * 1024,
+            max_frame_size: 64 * 1024,
+            max_retained_messages: 5000,
+            max_strikes: 50,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    Allowed,
+    Throttled(Duration),
+    Disconnect,
+}
+
+// Token buckets holding one second worth of messages and bytes
+pub struct RateLimiter {
+    limits: RateLimits,
+    message_tokens: f64,
+    byte_tokens: f64,
+    last_refill: Instant,
+    strikes: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            message_tokens: limits.messages_per_second as f64,
+            byte_tokens: limits.bytes_per_second as f64,
+            last_refill: now,
+            strikes: 0,
+        }
+    }
+
+    pub fn strikes(&self) -> u32 {
+        self.strikes
+    }
+
+    pub fn admit(&mut self, bytes: usize, now: Instant) -> Admission {
+        let message_rate = self.limits.messages_per_second.max(1) as f64;
+        let byte_rate = self.limits.bytes_per_second.max(1) as f64;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.last_refill = now;
+        self.message_tokens = (self.message_tokens + elapsed * message_rate).min(message_rate);
+        self.byte_tokens = (self.byte_tokens + elapsed * byte_rate).min(byte_rate);
+
+        self.message_tokens -= 1.0;
+        self.byte_tokens -= bytes as f64;
+        let wait = (-self.message_tokens / message_rate).max(-self.byte_tokens / byte_rate);
+        if wait <= 0.0 {
+            self.strikes = 0;
+            return Admission::Allowed;
+        }
+        self.strikes += 1;
+        if self.strikes > self.limits.max_strikes {
+            Admission::Disconnect
+        } else {
+            Admission::Throttled(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limits(messages_per_second: u32, bytes_per_second: u32) -> RateLimits {
+        RateLimits {
+            messages_per_second,
+            bytes_per_second,
+            max_strikes: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_burst_within_limits_is_allowed() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(limits(5, 1000), now);
+        for _ in 0..5 {
+            assert_eq!(limiter.admit(10, now), Admission::Allowed);
+        }
+    }
+
+    #[test]
+    fn test_message_flood_is_throttled_then_disconnected() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(limits(2, 1000), now);
+        assert_eq!(limiter.admit(1, now), Admission::Allowed);
+        assert_eq!(limiter.admit(1, now), Admission::Allowed);
+        assert_eq!(
+            limiter.admit(1, now),
+            Admission::Throttled(Duration::from_millis(500))
+        );
+        assert!(matches!(limiter.admit(1, now), Admission::Throttled(_)));
+        assert!(matches!(limiter.admit(1, now), Admission::Throttled(_)));
+        assert_eq!(limiter.admit(1, now), Admission::Disconnect);
+    }
+
+    #[test]
+    fn test_byte_flood_is_throttled() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(limits(100, 1000), now);
+        assert_eq!(limiter.admit(1000, now), Admission::Allowed);
+        assert_eq!(
+            limiter.admit(500, now),
+            Admission::Throttled(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn test_strikes_reset_once_peer_calms_down() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(limits(1, 1000), now);
+        assert_eq!(limiter.admit(1, now), Admission::Allowed);
+        assert!(matches!(limiter.admit(1, now), Admission::Throttled(_)));
+        assert_eq!(limiter.strikes(), 1);
+
+        let later = now + Duration::from_secs(5);
+        assert_eq!(limiter.admit(1, later), Admission::Allowed);
+        assert_eq!(limiter.strikes(), 0);
+    }
+}
@@ -1,33 +1,105 @@
 use std::{
-    borrow::Borrow,
-    collections::{LinkedList, VecDeque},
-    net::{TcpListener, TcpStream},
-    sync::{atomic::AtomicBool, Arc, Mutex},
+    collections::LinkedList,
+    io,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
     thread,
+    time::Duration,
 };
 
+use super::{
+    access::{AccessList, RejectReason},
+    frame,
+    identity::{self, Fingerprint, HANDSHAKE_TIMEOUT},
+    MessageType,
+};
+
+// Persistent accept errors like running out of file descriptors would otherwise spin
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+// Each handshake holds a thread for up to HANDSHAKE_TIMEOUT, past this many peers are refused
+const MAX_HANDSHAKES: usize = 32;
+
+type Pending = Arc<Mutex<LinkedList<TcpStream>>>;
+
 pub struct Listener {
     listener: Arc<Mutex<TcpListener>>,
-    pending_connections: Arc<Mutex<LinkedList<TcpStream>>>,
+    pending_connections: Pending,
     running: Arc<Mutex<bool>>,
+    access_list: Arc<AccessList>,
 }
 
 impl Listener {
-    pub fn new() -> Self {
+    pub fn new(access_list: AccessList) -> Self {
         Self {
             listener: Arc::new(Mutex::new(TcpListener::bind("127.0.0.1:0").unwrap())),
             pending_connections: Arc::new(Mutex::new(LinkedList::new())),
             running: Arc::new(Mutex::new(false)),
+            access_list: Arc::new(access_list),
         }
     }
     pub fn setup_thread(&self) {
-        let listener = Arc::clone(&self.listener);
+        // Accept on a clone so the blocking accept never holds the lock
+        let listener = self.listener.lock().unwrap().try_clone().unwrap();
         let running = Arc::clone(&self.running);
         let pending_connections = Arc::clone(&self.pending_connections);
+        let access_list = Arc::clone(&self.access_list);
+        *running.lock().unwrap() = true;
         thread::spawn(move || {
+            let mut backoff = ACCEPT_BACKOFF_MIN;
+            let handshakes = Arc::new(AtomicUsize::new(0));
             while *running.lock().unwrap() {
-                let (stream, _) = listener.lock().unwrap().accept().unwrap();
-                pending_connections.lock().unwrap().push_front(stream);
+                let (stream, addr) = match listener.accept() {
+                    Ok(accepted) => {
+                        backoff = ACCEPT_BACKOFF_MIN;
+                        accepted
+                    }
+                    Err(_) => {
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                        continue;
+                    }
+                };
+                // Dialers expect a challenge first whether or not we care about the answer
+                let Ok(challenge) = identity::send_challenge(&stream) else {
+                    continue;
+                };
+                if !access_list.uses_fingerprints() {
+                    admit(&access_list, &pending_connections, stream, addr, None);
+                    continue;
+                }
+                // No need to wait for an identity when the address alone is denied
+                if let Err(reason) = access_list.check(addr.ip(), None) {
+                    if !matches!(reason, RejectReason::Stranger(_)) {
+                        reject(&access_list, stream, addr, &reason);
+                        continue;
+                    }
+                }
+                if handshakes.fetch_add(1, SeqCst) >= MAX_HANDSHAKES {
+                    handshakes.fetch_sub(1, SeqCst);
+                    reject(&access_list, stream, addr, &RejectReason::Busy);
+                    continue;
+                }
+                // The handshake gets its own thread so a silent peer can't stall accepting
+                let access_list = Arc::clone(&access_list);
+                let pending_connections = Arc::clone(&pending_connections);
+                let handshakes = Arc::clone(&handshakes);
+                thread::spawn(move || {
+                    match read_identity(&stream, &challenge) {
+                        Ok(fingerprint) => admit(
+                            &access_list,
+                            &pending_connections,
+                            stream,
+                            addr,
+                            fingerprint,
+                        ),
+                        Err(reason) => reject(&access_list, stream, addr, &reason),
+                    }
+                    handshakes.fetch_sub(1, SeqCst);
+                });
             }
         });
     }
@@ -43,3 +115,165 @@ impl Listener {
             .to_string()
     }
 }
+
+fn admit(
+    access_list: &AccessList,
+    pending_connections: &Pending,
+    stream: TcpStream,
+    addr: SocketAddr,
+    fingerprint: Option<Fingerprint>,
+) {
+    match access_list.check(addr.ip(), fingerprint.as_ref()) {
+        Ok(()) => pending_connections.lock().unwrap().push_front(stream),
+        Err(reason) => reject(access_list, stream, addr, &reason),
+    }
+}
+
+fn reject(access_list: &AccessList, stream: TcpStream, addr: SocketAddr, reason: &RejectReason) {
+    access_list.log_blocked(addr, reason);
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+// The answer to our challenge. A peer that stays silent has no identity, one that sends
+// anything else or a signature that doesn't check out is turned away
+fn read_identity(
+    stream: &TcpStream,
+    challenge: &[u8],
+) -> Result<Option<Fingerprint>, RejectReason> {
+    if stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).is_err() {
+        return Ok(None);
+    }
+    let first = frame::read_frame(&mut &*stream, identity::ANSWER_LEN);
+    let _ = stream.set_read_timeout(None);
+    match first {
+        Ok((kind, answer)) if kind == MessageType::Identity as u8 => {
+            identity::verify(challenge, &answer)
+                .map(Some)
+                .ok_or(RejectReason::BadIdentity)
+        }
+        Ok(_) => Err(RejectReason::BadIdentity),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Err(RejectReason::BadIdentity),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, io::Write, process};
+
+    use identity::Identity;
+
+    use super::*;
+    use crate::networking::test::eventually;
+
+    fn blocked_log(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("tui_chat_{}_{}.log", name, process::id()))
+    }
+
+    fn logged(log_file: &std::path::Path, text: &str) -> Option<String> {
+        fs::read_to_string(log_file)
+            .ok()
+            .filter(|log| log.contains(text))
+    }
+
+    #[test]
+    fn test_allowed_connection_is_queued() {
+        let mut listener = Listener::new(AccessList {
+            reject_strangers: true,
+            allow: vec!["127.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        });
+        listener.setup_thread();
+        let _client = TcpStream::connect(listener.get_ip()).unwrap();
+        assert!(eventually(|| listener.pop()).is_some());
+    }
+
+    #[test]
+    fn test_blocked_connection_never_reaches_queue() {
+        let log_file = blocked_log("blocked");
+        let mut listener = Listener::new(AccessList {
+            deny: vec!["127.0.0.1".parse().unwrap()],
+            log_file: Some(log_file.clone()),
+            ..Default::default()
+        });
+        listener.setup_thread();
+        let _client = TcpStream::connect(listener.get_ip()).unwrap();
+        // The log is written before the stream would have been queued
+        assert!(eventually(|| logged(&log_file, "blocked 127.0.0.1")).is_some());
+        assert!(listener.pop().is_none());
+        let _ = fs::remove_file(log_file);
+    }
+
+    #[test]
+    fn test_known_fingerprint_gets_past_stranger_mode() {
+        let path = env::temp_dir().join(format!("tui_chat_listener_identity_{}", process::id()));
+        let identity = Identity::load_or_create(&path).unwrap();
+        let log_file = blocked_log("fingerprint");
+        let mut listener = Listener::new(AccessList {
+            reject_strangers: true,
+            allow_fingerprints: vec![identity.fingerprint()],
+            log_file: Some(log_file.clone()),
+            ..Default::default()
+        });
+        listener.setup_thread();
+
+        let mut friend = TcpStream::connect(listener.get_ip()).unwrap();
+        identity.answer_challenge(&mut friend).unwrap();
+        assert!(eventually(|| listener.pop()).is_some());
+
+        // Straight to a message instead of answering
+        let mut stranger = TcpStream::connect(listener.get_ip()).unwrap();
+        stranger
+            .write_all(&frame::encode(MessageType::NameChange, b"hi").unwrap())
+            .unwrap();
+        assert!(eventually(|| logged(&log_file, "identity challenge")).is_some());
+
+        // A recorded answer is no good for a fresh challenge
+        let replayed = identity.answer(&[0; identity::CHALLENGE_LEN]);
+        let mut impostor = TcpStream::connect(listener.get_ip()).unwrap();
+        frame::read_frame(&mut impostor, identity::CHALLENGE_LEN).unwrap();
+        impostor
+            .write_all(&frame::encode(MessageType::Identity, &replayed).unwrap())
+            .unwrap();
+        assert!(eventually(|| {
+            fs::read_to_string(&log_file)
+                .ok()
+                .filter(|log| log.matches("identity challenge").count() == 2)
+        })
+        .is_some());
+        assert!(listener.pop().is_none());
+        let _ = fs::remove_file(log_file);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_handshakes_are_capped() {
+        let path = env::temp_dir().join(format!("tui_chat_listener_capped_{}", process::id()));
+        let identity = Identity::load_or_create(&path).unwrap();
+        let log_file = blocked_log("capped");
+        let mut listener = Listener::new(AccessList {
+            reject_strangers: true,
+            allow_fingerprints: vec![identity.fingerprint()],
+            log_file: Some(log_file.clone()),
+            ..Default::default()
+        });
+        listener.setup_thread();
+
+        let silent: Vec<TcpStream> = (0..MAX_HANDSHAKES)
+            .map(|_| TcpStream::connect(listener.get_ip()).unwrap())
+            .collect();
+        let _refused = TcpStream::connect(listener.get_ip()).unwrap();
+        assert!(eventually(|| logged(&log_file, "too many handshakes")).is_some());
+
+        // Hanging up frees the slots again
+        drop(silent);
+        assert!(eventually(|| {
+            let mut friend = TcpStream::connect(listener.get_ip()).ok()?;
+            identity.answer_challenge(&mut friend).ok()?;
+            eventually(|| listener.pop())
+        })
+        .is_some());
+        let _ = fs::remove_file(log_file);
+        let _ = fs::remove_file(path);
+    }
+}
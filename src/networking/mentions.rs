@@ -0,0 +1,94 @@
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct MentionPattern(Regex);
+
+impl TryFrom<String> for MentionPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern)
+            .map(MentionPattern)
+            .map_err(|e| format!("invalid mention pattern '{}': {}", pattern, e))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mentions {
+    pub keywords: Vec<String>,
+    pub patterns: Vec<MentionPattern>,
+    // Our own display name, filled in from the top level settings
+    #[serde(skip)]
+    pub name: Option<String>,
+}
+
+impl Mentions {
+    pub fn matches(&self, text: &str) -> bool {
+        self.name
+            .iter()
+            .chain(&self.keywords)
+            .any(|keyword| Mentions::contains_word(text, keyword))
+            || self.patterns.iter().any(|pattern| pattern.0.is_match(text))
+    }
+
+    // Case-insensitive whole word match, so "al" doesn't fire on "also"
+    fn contains_word(text: &str, word: &str) -> bool {
+        if word.is_empty() {
+            return false;
+        }
+        let text = text.to_lowercase();
+        let word = word.to_lowercase();
+        text.match_indices(&word).any(|(start, _)| {
+            let end = start + word.len();
+            !text[..start].ends_with(char::is_alphanumeric)
+                && !text[end..].starts_with(char::is_alphanumeric)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mentions(keywords: &[&str]) -> Mentions {
+        Mentions {
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_keyword_matches_whole_words() {
+        let mentions = mentions(&["al", "deploy"]);
+        assert!(mentions.matches("hey Al, you there?"));
+        assert!(mentions.matches("DEPLOY is done"));
+        assert!(!mentions.matches("also this"));
+        assert!(!mentions.matches("redeployed"));
+    }
+
+    #[test]
+    fn test_no_keywords_never_match() {
+        assert!(!mentions(&[]).matches("anything"));
+        assert!(!mentions(&[""]).matches("anything"));
+    }
+
+    #[test]
+    fn test_display_name_and_patterns_match() {
+        let mentions: Mentions = toml::from_str(r#"patterns = ["(?i)incident-\\d+"]"#).unwrap();
+        let mentions = Mentions {
+            name: Some("Nikolas".to_string()),
+            ..mentions
+        };
+        assert!(mentions.matches("nikolas: look at this"));
+        assert!(mentions.matches("see INCIDENT-42"));
+        assert!(!mentions.matches("incident report"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        assert!(toml::from_str::<Mentions>(r#"patterns = ["("]"#).is_err());
+    }
+}
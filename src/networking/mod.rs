@@ -1,25 +1,53 @@
-use std::io::{Read, Write};
+use std::io::{self, Write};
+pub mod access;
+pub mod frame;
+pub mod identity;
+pub mod limits;
 pub mod listener;
+pub mod mentions;
+pub mod notify;
+pub mod payload;
+pub mod presence;
+pub mod reactions;
+pub mod sanitize;
 
-use std::u8;
 use std::{
-    net::TcpStream,
+    net::{Shutdown, SocketAddr, TcpStream},
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         Arc, Mutex,
     },
-    thread, time,
-    time::SystemTime,
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
+use limits::{Admission, RateLimiter, RateLimits};
+use mentions::Mentions;
+use notify::{Notification, NotifyHook, NotifyLevel};
+use payload::{Edit, MessageId, Text};
+use presence::Status;
+use reactions::Reaction;
+use serde::{Deserialize, Serialize};
+
 const SELF_NAME: &str = "Me";
+const SYSTEM_NAME: &str = "System";
+// Peers refresh their typing state well within this, so a stale one means they went away
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MessageType {
     Text = 0,
     NameChange = 1,
     Encryption = 2,
     Error = 3,
+    Typing = 4,
+    Presence = 5,
+    Edit = 6,
+    Retract = 7,
+    Reaction = 8,
+    Identity = 9,
+    Challenge = 10,
 }
 
 impl MessageType {
@@ -30,25 +58,84 @@ impl MessageType {
             1 => Some(MessageType::NameChange),
             2 => Some(MessageType::Encryption),
             3 => Some(MessageType::Error),
+            4 => Some(MessageType::Typing),
+            5 => Some(MessageType::Presence),
+            6 => Some(MessageType::Edit),
+            7 => Some(MessageType::Retract),
+            8 => Some(MessageType::Reaction),
+            9 => Some(MessageType::Identity),
+            10 => Some(MessageType::Challenge),
             _ => None, // Return None if the byte doesn’t match any known MessageType
         }
     }
 }
 
+// How a connection's frames travel. Encryption frames aren't handled yet, so for now
+// every connection is plain TCP
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Encryption {
+    Plain,
+}
+
+impl Encryption {
+    pub fn label(self) -> &'static str {
+        match self {
+            Encryption::Plain => "unencrypted",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
+    pub id: MessageId,
+    pub reply_to: Option<MessageId>,
     pub time: SystemTime,
     pub sent_by_self: bool,
+    // Raised on this side, like warnings. Unlike the sender name a peer can't fake it
+    pub system: bool,
     pub sender_name: String,
     pub message_type: MessageType,
     pub content: String,
+    pub is_mention: bool,
+    // When the sender last changed the content
+    pub edited: Option<SystemTime>,
+    // Retracted by the sender, only a tombstone is left
+    pub deleted: bool,
+    pub reactions: Vec<Reaction>,
 }
 
+impl Message {
+    fn retract(&mut self) {
+        self.content.clear();
+        self.reactions.clear();
+        self.deleted = true;
+    }
+
+    // System messages and tombstones can't be reacted to
+    fn can_react(&self) -> bool {
+        !self.system && !self.deleted
+    }
+
+    fn own(&self) -> bool {
+        self.sent_by_self && !self.system && !self.deleted
+    }
+}
+
+// All state is shared, so a clone refers to the same connection
+#[derive(Clone)]
 pub struct Connection {
     pub name: Arc<Mutex<String>>,
     stream: Arc<Mutex<TcpStream>>,
     is_alive: Arc<AtomicBool>,
+    encryption: Encryption,
     pub messages: Arc<Mutex<Vec<Message>>>,
+    limits: RateLimits,
+    peer_typing: Arc<Mutex<Option<Instant>>>,
+    peer_status: Arc<Mutex<Status>>,
+    last_read: Arc<Mutex<SystemTime>>,
+    mentions: Arc<Mentions>,
+    notify_level: Arc<Mutex<NotifyLevel>>,
+    notify_hook: Option<NotifyHook>,
 }
 
 impl Connection {
@@ -68,118 +155,495 @@ impl Connection {
             name,
             stream: arc_stream,
             is_alive,
+            encryption: Encryption::Plain,
             messages,
+            limits: RateLimits::default(),
+            peer_typing: Arc::new(Mutex::new(None)),
+            peer_status: Arc::new(Mutex::new(Status::default())),
+            last_read: Arc::new(Mutex::new(SystemTime::now())),
+            mentions: Arc::new(Mentions::default()),
+            notify_level: Arc::new(Mutex::new(NotifyLevel::default())),
+            notify_hook: None,
         }
     }
 
+    pub fn with_limits(mut self, limits: RateLimits) -> Connection {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_mentions(mut self, mentions: Arc<Mentions>) -> Connection {
+        self.mentions = mentions;
+        self
+    }
+
+    pub fn with_notify(mut self, level: NotifyLevel, hook: NotifyHook) -> Connection {
+        self.notify_level = Arc::new(Mutex::new(level));
+        self.notify_hook = Some(hook);
+        self
+    }
+
+    pub fn notify_level(&self) -> NotifyLevel {
+        *self.notify_level.lock().unwrap()
+    }
+    pub fn set_notify_level(&self, level: NotifyLevel) {
+        *self.notify_level.lock().unwrap() = level;
+    }
+
     pub fn get_name(&self) -> String {
         self.name.lock().unwrap().clone()
     }
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.stream.lock().unwrap().peer_addr().ok()
+    }
+    pub fn encryption(&self) -> Encryption {
+        self.encryption
+    }
+    pub fn is_alive(&self) -> bool {
+        self.is_alive.load(Relaxed)
+    }
+    pub fn is_peer_typing(&self) -> bool {
+        self.peer_typing
+            .lock()
+            .unwrap()
+            .is_some_and(|since| since.elapsed() < TYPING_TIMEOUT)
+    }
+    pub fn peer_status(&self) -> Status {
+        self.peer_status.lock().unwrap().clone()
+    }
+    pub fn same_as(&self, other: &Connection) -> bool {
+        Arc::ptr_eq(&self.messages, &other.messages)
+    }
+    pub fn last_read(&self) -> SystemTime {
+        *self.last_read.lock().unwrap()
+    }
+    pub fn mark_read(&self) {
+        *self.last_read.lock().unwrap() = SystemTime::now();
+    }
+    // Messages are in arrival order, so only the tail past last_read is scanned
+    pub fn unread_count(&self) -> usize {
+        let last_read = self.last_read();
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take_while(|m| m.time > last_read)
+            .filter(|m| !m.sent_by_self && !m.system)
+            .count()
+    }
+    pub fn unread_mentions(&self) -> usize {
+        let last_read = self.last_read();
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take_while(|m| m.time > last_read)
+            .filter(|m| m.is_mention)
+            .count()
+    }
+    pub fn last_activity(&self) -> Option<SystemTime> {
+        self.messages.lock().unwrap().last().map(|m| m.time)
+    }
     pub fn register_listener(connection: Arc<Mutex<Self>>) {
-        let mut conn = Arc::clone(&connection);
+        let conn = Arc::clone(&connection);
 
         thread::spawn(move || {
-            let mut buffer = [0; 512];
-            while conn.lock().unwrap().is_alive.load(Relaxed) {
-                dbg!("Incoming message...");
-                let mut stream = conn
-                    .lock()
-                    .unwrap()
-                    .stream
-                    .clone()
-                    .lock()
-                    .unwrap()
-                    .try_clone()
-                    .unwrap();
-                match stream.read(&mut buffer) {
-                    Ok(0) => {
-                        conn.lock().unwrap().is_alive.store(false, Relaxed);
-                    }
-                    Ok(n) => {
+            let (mut stream, limits) = {
+                let conn = conn.lock().unwrap();
+                let stream = conn.stream.lock().unwrap().try_clone().unwrap();
+                (stream, conn.limits)
+            };
+            let mut limiter = RateLimiter::new(limits, Instant::now());
+            while conn.lock().unwrap().is_alive() {
+                match frame::read_frame(&mut stream, limits.max_frame_size) {
+                    Ok((message_type, payload)) => {
+                        match limiter.admit(payload.len(), Instant::now()) {
+                            Admission::Allowed => {}
+                            Admission::Throttled(wait) => {
+                                if limiter.strikes() == 1 {
+                                    conn.lock().unwrap().register_warning(
+                                        "Peer is sending too fast, throttling incoming messages",
+                                    );
+                                }
+                                thread::sleep(wait);
+                            }
+                            Admission::Disconnect => {
+                                let mut conn = conn.lock().unwrap();
+                                conn.register_warning("Peer kept flooding, disconnected");
+                                conn.disconnect();
+                                break;
+                            }
+                        }
                         conn.lock()
                             .unwrap()
-                            .handle_incoming_data(Vec::from(&buffer[..n]));
+                            .handle_incoming_data(message_type, payload);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        let mut conn = conn.lock().unwrap();
+                        conn.register_warning(&format!("Disconnected: {}", e));
+                        conn.disconnect();
+                    }
+                    Err(e) => {
+                        let conn = conn.lock().unwrap();
+                        if conn.is_alive() && e.kind() != io::ErrorKind::UnexpectedEof {
+                            conn.register_warning(&format!("Disconnected: {}", e));
+                        }
+                        conn.is_alive.store(false, Relaxed);
                     }
-                    Err(e) => conn
-                        .lock()
-                        .unwrap()
-                        .register_incoming_message(format!("{}", e), MessageType::Error),
                 }
             }
         });
     }
 
-    fn handle_incoming_data(&mut self, data: Vec<u8>) {
-        let message_type_byte = data[0];
+    fn handle_incoming_data(&mut self, message_type_byte: u8, data: Vec<u8>) {
         if let Some(message_type) = MessageType::from_u8(message_type_byte) {
             match message_type {
                 MessageType::Text | MessageType::Error => {
-                    let message = String::from_utf8_lossy(&data[1..]);
-                    self.register_incoming_message(message.to_string(), message_type);
+                    *self.peer_typing.lock().unwrap() = None;
+                    if let Some(mut text) = Text::decode(&data) {
+                        text.content = sanitize::content(&text.content);
+                        self.register_incoming_message(text, message_type);
+                    }
                 }
                 MessageType::NameChange => {
-                    let new_name = String::from_utf8_lossy(&data[1..]);
+                    let new_name = sanitize::line(&String::from_utf8_lossy(&data));
                     let mut name_guard = self.name.lock().unwrap();
-                    *name_guard = new_name.to_string();
+                    *name_guard = new_name;
                 }
                 MessageType::Encryption => {
                     println!("Encryption not yet implemented");
                 }
+                // Part of the handshake, which is over before the stream gets here. Listeners
+                // without fingerprint rules get an answer they don't need
+                MessageType::Identity | MessageType::Challenge => {}
+                MessageType::Typing => {
+                    let typing = data.first().is_some_and(|b| *b != 0);
+                    *self.peer_typing.lock().unwrap() = typing.then(Instant::now);
+                }
+                MessageType::Presence => {
+                    if let Some(mut status) = Status::decode(&data) {
+                        status.text = sanitize::line(&status.text);
+                        *self.peer_status.lock().unwrap() = status;
+                    }
+                }
+                MessageType::Edit => {
+                    if let Some(edit) = Edit::decode(&data) {
+                        let content = sanitize::content(&edit.content);
+                        self.modify_peer_message(edit.id, |m| {
+                            m.content = content;
+                            m.edited = Some(SystemTime::now());
+                        });
+                    }
+                }
+                MessageType::Retract => {
+                    if let Some(id) = payload::decode_retract(&data) {
+                        self.modify_peer_message(id, Message::retract);
+                    }
+                }
+                MessageType::Reaction => {
+                    let Some((id, emoji)) = payload::decode_reaction(&data) else {
+                        return;
+                    };
+                    let Some(emoji) = reactions::normalize(&emoji) else {
+                        return;
+                    };
+                    let mut messages = self.messages.lock().unwrap();
+                    if let Some(message) = messages.iter_mut().find(|m| m.id == id && m.can_react())
+                    {
+                        reactions::toggle(&mut message.reactions, &emoji, false);
+                    }
+                }
             }
         }
     }
 
-    fn register_incoming_message(&mut self, message: String, message_type: MessageType) {
+    // Peers can only change what they sent themselves
+    fn modify_peer_message(&self, id: MessageId, change: impl FnOnce(&mut Message)) {
         let mut messages = self.messages.lock().unwrap();
+        if let Some(message) = messages
+            .iter_mut()
+            .find(|m| m.id == id && !m.sent_by_self && !m.system && !m.deleted)
+        {
+            change(message);
+            message.is_mention = message.message_type == MessageType::Text
+                && !message.deleted
+                && self.mentions.matches(&message.content);
+        }
+    }
+
+    fn register_incoming_message(&self, text: Text, message_type: MessageType) {
         let name = self.name.lock().unwrap().clone();
-        messages.push(Message {
+        let message = Message {
+            id: text.id,
+            reply_to: text.reply_to,
             time: SystemTime::now(),
             sent_by_self: false,
-            sender_name: name,
+            system: false,
+            sender_name: name.clone(),
             message_type,
-            content: message,
+            is_mention: message_type == MessageType::Text && self.mentions.matches(&text.content),
+            content: text.content,
+            edited: None,
+            deleted: false,
+            reactions: vec![],
+        };
+        self.push_message(message.clone());
+        if let Some(hook) = &self.notify_hook {
+            if message_type == MessageType::Text && self.notify_level().allows(&message) {
+                hook(Notification {
+                    connection: self.clone(),
+                    conversation: name,
+                    message,
+                });
+            }
+        }
+    }
+
+    pub fn register_warning(&self, warning: &str) {
+        self.push_message(Message {
+            id: payload::new_id(),
+            reply_to: None,
+            time: SystemTime::now(),
+            sent_by_self: false,
+            system: true,
+            sender_name: SYSTEM_NAME.to_string(),
+            message_type: MessageType::Error,
+            content: warning.to_string(),
+            is_mention: false,
+            edited: None,
+            deleted: false,
+            reactions: vec![],
         });
     }
 
+    // Oldest messages are dropped once the retention cap is reached
+    fn push_message(&self, message: Message) {
+        let mut messages = self.messages.lock().unwrap();
+        messages.push(message);
+        let overflow = messages
+            .len()
+            .saturating_sub(self.limits.max_retained_messages);
+        messages.drain(..overflow);
+    }
+
     pub fn disconnect(&mut self) {
-        self.is_alive
-            .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.is_alive.store(false, Relaxed);
+        // Wakes up the reading thread if it is blocked on the socket
+        let _ = self.stream.lock().unwrap().shutdown(Shutdown::Both);
+    }
+
+    // The peer would drop the connection over a frame past its limit, assuming it's ours
+    fn write_frame(&self, message_type: MessageType, payload: &[u8]) -> io::Result<()> {
+        if payload.len() > self.limits.max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "not sent, {} bytes exceeds the {} byte limit",
+                    payload.len(),
+                    self.limits.max_frame_size
+                ),
+            ));
+        }
+        self.stream
+            .lock()
+            .unwrap()
+            .write_all(&frame::encode(message_type, payload)?)
+    }
+
+    // Typing state is best effort, a failed write shows up on the next message
+    pub fn send_typing(&mut self, typing: bool) {
+        if self.is_alive() {
+            let _ = self.write_frame(MessageType::Typing, &[typing as u8]);
+        }
+    }
+
+    pub fn send_name(&mut self, name: &str) {
+        if self.is_alive() {
+            let _ = self.write_frame(MessageType::NameChange, name.as_bytes());
+        }
+    }
+
+    pub fn send_status(&mut self, status: &Status) {
+        if self.is_alive() {
+            let _ = self.write_frame(MessageType::Presence, &status.encode());
+        }
     }
 
     pub fn send_message(&mut self, message: String, message_type: MessageType) {
-        if !self.is_alive.load(Relaxed) {
+        self.send_text(message, message_type, None);
+    }
+
+    pub fn send_edit(&mut self, id: MessageId, content: String) {
+        let edit = Edit { id, content };
+        self.change_own_message(id, MessageType::Edit, &edit.encode(), |m| {
+            m.content = edit.content.clone();
+            m.edited = Some(SystemTime::now());
+        });
+    }
+
+    pub fn send_retract(&mut self, id: MessageId) {
+        self.change_own_message(
+            id,
+            MessageType::Retract,
+            &payload::encode_retract(id),
+            Message::retract,
+        );
+    }
+
+    fn change_own_message(
+        &mut self,
+        id: MessageId,
+        message_type: MessageType,
+        payload: &[u8],
+        change: impl FnOnce(&mut Message),
+    ) {
+        let own = self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| m.id == id && m.own());
+        if !own {
+            self.register_warning("Only your own messages can be edited or deleted");
             return;
         }
-        let mut stream = self.stream.lock().unwrap();
-        let mut buffer = vec![message_type as u8];
-        buffer.extend(message.bytes());
-        let mut messages = self.messages.lock().unwrap();
-        if let Err(e) = stream.write(&buffer) {
-            messages.push(Message {
+        if !self.is_alive() {
+            return;
+        }
+        match self.write_frame(message_type, payload) {
+            Ok(()) => {
+                if let Some(message) = self
+                    .messages
+                    .lock()
+                    .unwrap()
+                    .iter_mut()
+                    .find(|m| m.id == id && m.own())
+                {
+                    change(message);
+                }
+            }
+            Err(e) => self.register_warning(&e.to_string()),
+        }
+    }
+
+    // Toggles our reaction, on any message that isn't from the system
+    pub fn send_reaction(&mut self, id: MessageId, reaction: &str) {
+        let Some(emoji) = reactions::normalize(reaction) else {
+            self.register_warning(&format!("'{}' is not a reaction", reaction));
+            return;
+        };
+        let can_react = self
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|m| m.id == id && m.can_react());
+        if !can_react || !self.is_alive() {
+            return;
+        }
+        match self.write_frame(MessageType::Reaction, &payload::encode_reaction(id, &emoji)) {
+            Ok(()) => {
+                if let Some(message) = self
+                    .messages
+                    .lock()
+                    .unwrap()
+                    .iter_mut()
+                    .find(|m| m.id == id && m.can_react())
+                {
+                    reactions::toggle(&mut message.reactions, &emoji, true);
+                }
+            }
+            Err(e) => self.register_warning(&e.to_string()),
+        }
+    }
+
+    pub fn send_reply(&mut self, message: String, reply_to: MessageId) {
+        self.send_text(message, MessageType::Text, Some(reply_to));
+    }
+
+    fn send_text(
+        &mut self,
+        message: String,
+        message_type: MessageType,
+        reply_to: Option<MessageId>,
+    ) {
+        if !self.is_alive() {
+            return;
+        }
+        let text = Text {
+            id: payload::new_id(),
+            reply_to,
+            content: message,
+        };
+        if let Err(e) = self.write_frame(message_type, &text.encode()) {
+            self.push_message(Message {
+                id: payload::new_id(),
+                reply_to: None,
                 time: SystemTime::now(),
                 sent_by_self: true,
+                system: true,
                 sender_name: SELF_NAME.to_string(),
                 message_type: MessageType::Error,
                 content: format!("{}", e),
+                is_mention: false,
+                edited: None,
+                deleted: false,
+                reactions: vec![],
             })
         } else {
-            messages.push(Message {
+            self.push_message(Message {
+                id: text.id,
+                reply_to,
                 time: SystemTime::now(),
                 sent_by_self: true,
+                system: false,
                 sender_name: SELF_NAME.to_string(),
-                message_type: MessageType::Text,
-                content: message,
+                message_type,
+                content: text.content,
+                is_mention: false,
+                edited: None,
+                deleted: false,
+                reactions: vec![],
             });
         }
     }
 }
 
 #[cfg(test)]
-mod test {
-    use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+pub(crate) mod test {
+    use std::{net::TcpListener, time};
 
     use super::*;
 
+    const DEADLINE: Duration = Duration::from_secs(5);
+
+    // Polls until the check comes back with something, giving up after the deadline
+    pub fn eventually<T>(mut check: impl FnMut() -> Option<T>) -> Option<T> {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = check() {
+                return Some(value);
+            }
+            if start.elapsed() > DEADLINE {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn wait_until(mut done: impl FnMut() -> bool) {
+        assert!(
+            eventually(|| done().then_some(())).is_some(),
+            "timed out waiting"
+        );
+    }
+
+    fn message_count(conn: &Connection) -> usize {
+        conn.messages.lock().unwrap().len()
+    }
+
     fn mock_tcpstream() -> (TcpStream, TcpStream) {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let port = listener.local_addr().expect("").port();
@@ -191,10 +655,19 @@ mod test {
 
         (stream, stream2)
     }
+
+    fn text(content: &str) -> Text {
+        Text {
+            id: payload::new_id(),
+            reply_to: None,
+            content: content.to_string(),
+        }
+    }
+
     #[test]
     fn test_new_connection_sets_defaults_correctly() {
         // Arrange
-        let (stream1, stream2) = mock_tcpstream(); // Mock a TcpStream
+        let (stream1, _stream2) = mock_tcpstream(); // Mock a TcpStream
 
         // Act
         let conn = Connection::new(stream1);
@@ -208,7 +681,7 @@ mod test {
         assert!(conn.stream.lock().is_ok());
 
         // Check if is_alive is set to true
-        assert_eq!(conn.is_alive.load(Relaxed), true);
+        assert!(conn.is_alive.load(Relaxed));
 
         // Ensure the messages list is empty at the start
         let messages = conn.messages.lock().unwrap();
@@ -216,6 +689,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::map_clone)]
     fn test_connections() {
         let (stream1, stream2) = mock_tcpstream();
         let conn1 = Connection::new(stream1);
@@ -256,4 +730,388 @@ mod test {
         assert_eq!(msg2[0].message_type, MessageType::Text);
         assert_eq!(msg2[0].content, "nigga");
     }
+
+    #[test]
+    fn test_retained_messages_are_capped() {
+        let (stream1, _stream2) = mock_tcpstream();
+        let conn = Connection::new(stream1).with_limits(RateLimits {
+            max_retained_messages: 3,
+            ..Default::default()
+        });
+        for i in 0..5 {
+            conn.register_incoming_message(text(&i.to_string()), MessageType::Text);
+        }
+        let contents: Vec<String> = conn
+            .messages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|m| m.content.clone())
+            .collect();
+        assert_eq!(contents, vec!["2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_flooding_peer_is_disconnected() {
+        let (mut stream1, stream2) = mock_tcpstream();
+        let conn = Connection::new(stream2).with_limits(RateLimits {
+            messages_per_second: 10,
+            max_strikes: 2,
+            ..Default::default()
+        });
+        Connection::register_listener(Arc::new(Mutex::new(conn.clone())));
+
+        for _ in 0..20 {
+            if stream1
+                .write_all(&frame::encode(MessageType::Text, b"spam").unwrap())
+                .is_err()
+            {
+                break;
+            }
+        }
+        wait_until(|| !conn.is_alive());
+
+        let messages = conn.messages.lock().unwrap();
+        let warnings: Vec<&Message> = messages.iter().filter(|m| m.system).collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].content.contains("disconnected"));
+    }
+
+    #[test]
+    fn test_oversized_frame_disconnects() {
+        let (mut stream1, stream2) = mock_tcpstream();
+        let conn = Connection::new(stream2).with_limits(RateLimits {
+            max_frame_size: 16,
+            ..Default::default()
+        });
+        Connection::register_listener(Arc::new(Mutex::new(conn.clone())));
+
+        stream1
+            .write_all(&frame::encode(MessageType::Text, &[b'a'; 17]).unwrap())
+            .unwrap();
+        wait_until(|| !conn.is_alive());
+        assert_eq!(message_count(&conn), 1);
+    }
+
+    #[test]
+    fn test_read_errors_are_local_warnings() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn = Connection::new(stream2);
+        *conn.name.lock().unwrap() = "bob".to_string();
+        Connection::register_listener(Arc::new(Mutex::new(conn.clone())));
+        // Closing with unread data resets the connection rather than ending it cleanly
+        conn.send_message("unread".to_string(), MessageType::Text);
+        drop(stream1);
+        wait_until(|| !conn.is_alive());
+        let messages = conn.messages.lock().unwrap();
+        let warning = messages
+            .iter()
+            .find(|m| m.message_type == MessageType::Error);
+        assert!(warning.is_some_and(|m| m.system && m.sender_name == SYSTEM_NAME));
+    }
+
+    #[test]
+    fn test_oversized_message_is_not_sent() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1).with_limits(RateLimits {
+            max_frame_size: 64,
+            ..Default::default()
+        });
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_message("a".repeat(100), MessageType::Text);
+        conn1.send_message("ok".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn2) == 1);
+
+        assert!(conn1.is_alive());
+        let sent = conn1.messages.lock().unwrap();
+        assert_eq!(sent[0].message_type, MessageType::Error);
+        assert!(sent[0].system);
+        assert!(sent[0].content.contains("64 byte limit"));
+        assert_eq!(conn2.messages.lock().unwrap()[0].content, "ok");
+    }
+
+    #[test]
+    fn test_typing_state_is_shown_and_cleared() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_typing(true);
+        wait_until(|| conn2.is_peer_typing());
+        assert!(conn2.messages.lock().unwrap().is_empty());
+
+        conn1.send_message("done".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn2) == 1);
+        assert!(!conn2.is_peer_typing());
+
+        conn1.send_typing(true);
+        conn1.send_typing(false);
+        // Frames are handled in order, so once the status is in so is the typing state
+        let status = Status {
+            presence: presence::Presence::Away,
+            text: String::new(),
+        };
+        conn1.send_status(&status);
+        wait_until(|| conn2.peer_status() == status);
+        assert!(!conn2.is_peer_typing());
+    }
+
+    #[test]
+    fn test_status_is_propagated() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        let status = Status {
+            presence: presence::Presence::Away,
+            text: "lunch".to_string(),
+        };
+        conn1.send_status(&status);
+        wait_until(|| conn2.peer_status() == status);
+
+        assert!(conn2.messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unread_count_follows_last_read() {
+        let (stream1, _stream2) = mock_tcpstream();
+        let mut conn = Connection::new(stream1);
+        conn.register_incoming_message(text("one"), MessageType::Text);
+        conn.register_incoming_message(text("two"), MessageType::Text);
+        conn.send_message("mine".to_string(), MessageType::Text);
+        assert_eq!(conn.unread_count(), 2);
+
+        conn.mark_read();
+        assert_eq!(conn.unread_count(), 0);
+
+        conn.register_incoming_message(text("three"), MessageType::Text);
+        assert_eq!(conn.unread_count(), 1);
+        assert!(conn.last_activity().unwrap() > conn.last_read());
+        assert_eq!(conn.unread_mentions(), 0);
+    }
+
+    #[test]
+    fn test_name_change_is_applied() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_name("alice");
+        wait_until(|| conn2.get_name() == "alice");
+
+        assert!(conn2.messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reply_carries_parent_id() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_message("lunch?".to_string(), MessageType::Text);
+        let parent = conn1.messages.lock().unwrap()[0].id;
+        conn1.send_reply("12:30 works".to_string(), parent);
+        wait_until(|| message_count(&conn2) == 2);
+
+        let sent = conn1.messages.lock().unwrap();
+        let received = conn2.messages.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].id, parent);
+        assert_eq!(received[1].id, sent[1].id);
+        assert_eq!(received[1].reply_to, Some(parent));
+        assert_eq!(received[0].reply_to, None);
+    }
+
+    #[test]
+    fn test_edit_and_retract_update_both_sides() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_message("teh plan".to_string(), MessageType::Text);
+        conn1.send_message("oops, wrong chat".to_string(), MessageType::Text);
+        let (first, second) = {
+            let sent = conn1.messages.lock().unwrap();
+            (sent[0].id, sent[1].id)
+        };
+        conn1.send_edit(first, "the plan".to_string());
+        conn1.send_retract(second);
+        wait_until(|| {
+            conn2
+                .messages
+                .lock()
+                .unwrap()
+                .get(1)
+                .is_some_and(|m| m.deleted)
+        });
+
+        for conn in [&conn1, &conn2] {
+            let messages = conn.messages.lock().unwrap();
+            assert_eq!(messages[0].content, "the plan");
+            assert!(messages[0].edited.is_some());
+            assert!(messages[1].deleted);
+            assert!(messages[1].content.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_only_own_messages_can_be_changed() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let mut conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn1.clone())));
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn2.send_message("my words".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn1) == 1);
+        let id = conn2.messages.lock().unwrap()[0].id;
+
+        // The UI refuses, and a forged frame is ignored by the other side
+        conn1.send_edit(id, "your words".to_string());
+        conn1
+            .write_frame(
+                MessageType::Edit,
+                &Edit {
+                    id,
+                    content: "your words".to_string(),
+                }
+                .encode(),
+            )
+            .unwrap();
+        conn1
+            .write_frame(MessageType::Retract, &payload::encode_retract(id))
+            .unwrap();
+        conn1.send_message("done".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn2) == 2);
+
+        assert_eq!(conn1.messages.lock().unwrap()[0].content, "my words");
+        let messages = conn2.messages.lock().unwrap();
+        assert_eq!(messages[0].content, "my words");
+        assert!(messages[0].edited.is_none());
+        assert!(!messages[0].deleted);
+    }
+
+    #[test]
+    fn test_peer_named_system_is_still_a_peer() {
+        let (stream1, _stream2) = mock_tcpstream();
+        let mut conn = Connection::new(stream1);
+        conn.register_warning("only for us");
+        conn.handle_incoming_data(MessageType::NameChange as u8, SYSTEM_NAME.into());
+        conn.register_incoming_message(text("hi"), MessageType::Text);
+        let (notice, peer) = {
+            let messages = conn.messages.lock().unwrap();
+            (messages[0].id, messages[1].id)
+        };
+
+        for id in [notice, peer] {
+            let edit = Edit {
+                id,
+                content: "changed".to_string(),
+            };
+            conn.handle_incoming_data(MessageType::Edit as u8, edit.encode());
+            conn.handle_incoming_data(
+                MessageType::Reaction as u8,
+                payload::encode_reaction(id, "👀"),
+            );
+        }
+        // Our own reaction and retraction don't reach the notice either
+        conn.send_reaction(notice, "👀");
+        conn.send_retract(notice);
+
+        let messages = conn.messages.lock().unwrap();
+        assert_eq!(messages[0].content, "only for us");
+        assert!(messages[0].reactions.is_empty());
+        assert!(!messages[0].deleted);
+        assert_eq!(messages[1].sender_name, SYSTEM_NAME);
+        assert!(!messages[1].system);
+        assert_eq!(messages[1].content, "changed");
+        assert_eq!(messages[1].reactions.len(), 1);
+    }
+
+    #[test]
+    fn test_reactions_toggle_on_both_sides() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let mut conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn1.clone())));
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_message("shipped it".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn2) == 1);
+        let id = conn1.messages.lock().unwrap()[0].id;
+
+        conn2.send_reaction(id, ":tada:");
+        conn1.send_reaction(id, "🎉");
+        conn2.send_reaction(id, "👀");
+        conn2.send_reaction(id, "👀");
+        // Each side's message goes after its reactions, so it arriving means they did
+        conn1.send_message("done".to_string(), MessageType::Text);
+        conn2.send_message("done".to_string(), MessageType::Text);
+        wait_until(|| message_count(&conn1) == 3 && message_count(&conn2) == 3);
+
+        for conn in [&conn1, &conn2] {
+            let messages = conn.messages.lock().unwrap();
+            let reactions = &messages[0].reactions;
+            assert_eq!(reactions.len(), 2);
+            assert!(reactions.iter().all(|r| r.emoji == "🎉"));
+            assert_eq!(reactions.iter().filter(|r| r.by_self).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_peer_strings_are_sanitized() {
+        let (stream1, stream2) = mock_tcpstream();
+        let mut conn1 = Connection::new(stream1);
+        let conn2 = Connection::new(stream2);
+        Connection::register_listener(Arc::new(Mutex::new(conn2.clone())));
+
+        conn1.send_name("Me\n\x1b[31mSystem");
+        conn1.send_message(
+            "\x1b]0;owned\x07hi \u{202e}txt.exe".to_string(),
+            MessageType::Text,
+        );
+        wait_until(|| message_count(&conn2) == 1);
+
+        assert_eq!(conn2.get_name(), "Me ␛[31mSystem");
+        let messages = conn2.messages.lock().unwrap();
+        assert_eq!(messages[0].content, "␛]0;owned␇hi ⟦RLO⟧txt.exe");
+        assert_eq!(messages[0].sender_name, "Me ␛[31mSystem");
+    }
+
+    #[test]
+    fn test_notify_hook_respects_level() {
+        let (stream1, _stream2) = mock_tcpstream();
+        let notified = Arc::new(Mutex::new(vec![]));
+        let sink = Arc::clone(&notified);
+        let conn = Connection::new(stream1)
+            .with_mentions(Arc::new(Mentions {
+                keywords: vec!["bob".to_string()],
+                ..Default::default()
+            }))
+            .with_notify(
+                NotifyLevel::All,
+                Arc::new(move |n: Notification| {
+                    sink.lock().unwrap().push(n.message.content);
+                }),
+            );
+
+        conn.register_incoming_message(text("hello"), MessageType::Text);
+        conn.set_notify_level(NotifyLevel::Mentions);
+        conn.register_incoming_message(text("unrelated"), MessageType::Text);
+        conn.register_incoming_message(text("ping bob"), MessageType::Text);
+        conn.set_notify_level(NotifyLevel::Muted);
+        conn.register_incoming_message(text("bob again"), MessageType::Text);
+
+        assert_eq!(*notified.lock().unwrap(), vec!["hello", "ping bob"]);
+        let messages = conn.messages.lock().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert!(messages[2].is_mention && messages[3].is_mention);
+    }
 }
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::{Connection, Message};
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    Mentions,
+    Muted,
+}
+
+impl NotifyLevel {
+    pub fn allows(&self, message: &Message) -> bool {
+        match self {
+            NotifyLevel::All => true,
+            NotifyLevel::Mentions => message.is_mention,
+            NotifyLevel::Muted => false,
+        }
+    }
+}
+
+pub struct Notification {
+    pub connection: Connection,
+    pub conversation: String,
+    pub message: Message,
+}
+
+// Called from the connection's reader thread, so it should only hand the
+// notification over to the UI and return
+pub type NotifyHook = Arc<dyn Fn(Notification) + Send + Sync>;
@@ -0,0 +1,150 @@
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+// Picked by whoever sends the message, so both sides refer to it the same way
+pub type MessageId = u64;
+
+// Zero is never handed out, on the wire it stands for "no message"
+pub fn new_id() -> MessageId {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Relaxed));
+    hasher.finish().max(1)
+}
+
+fn read_id(data: &[u8]) -> Option<(MessageId, &[u8])> {
+    let (id, rest) = data.split_first_chunk::<8>()?;
+    Some((u64::from_be_bytes(*id), rest))
+}
+
+// Text and Error frames: [id u64][reply_to u64, 0 for none][utf8 content]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Text {
+    pub id: MessageId,
+    pub reply_to: Option<MessageId>,
+    pub content: String,
+}
+
+impl Text {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(16 + self.content.len());
+        buffer.extend(self.id.to_be_bytes());
+        buffer.extend(self.reply_to.unwrap_or(0).to_be_bytes());
+        buffer.extend(self.content.bytes());
+        buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Text> {
+        let (id, rest) = read_id(data)?;
+        let (reply_to, content) = read_id(rest)?;
+        Some(Text {
+            id,
+            reply_to: (reply_to != 0).then_some(reply_to),
+            content: String::from_utf8_lossy(content).to_string(),
+        })
+    }
+}
+
+// Edit frames: [id u64][utf8 content], the new text replaces the old one
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Edit {
+    pub id: MessageId,
+    pub content: String,
+}
+
+impl Edit {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(8 + self.content.len());
+        buffer.extend(self.id.to_be_bytes());
+        buffer.extend(self.content.bytes());
+        buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Edit> {
+        let (id, content) = read_id(data)?;
+        Some(Edit {
+            id,
+            content: String::from_utf8_lossy(content).to_string(),
+        })
+    }
+}
+
+// Retract frames are just the id of the message to take back
+pub fn encode_retract(id: MessageId) -> Vec<u8> {
+    id.to_be_bytes().to_vec()
+}
+
+pub fn decode_retract(data: &[u8]) -> Option<MessageId> {
+    match read_id(data)? {
+        (id, []) => Some(id),
+        _ => None,
+    }
+}
+
+// Reaction frames: [id u64][utf8 emoji], sending the same one again takes it back
+pub fn encode_reaction(id: MessageId, emoji: &str) -> Vec<u8> {
+    let mut buffer = id.to_be_bytes().to_vec();
+    buffer.extend(emoji.bytes());
+    buffer
+}
+
+pub fn decode_reaction(data: &[u8]) -> Option<(MessageId, String)> {
+    let (id, emoji) = read_id(data)?;
+    Some((id, String::from_utf8_lossy(emoji).to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_roundtrip() {
+        let text = Text {
+            id: new_id(),
+            reply_to: Some(42),
+            content: "sounds good".to_string(),
+        };
+        assert_eq!(Text::decode(&text.encode()), Some(text.clone()));
+        let text = Text {
+            reply_to: None,
+            ..text
+        };
+        assert_eq!(Text::decode(&text.encode()), Some(text));
+    }
+
+    #[test]
+    fn test_truncated_text_is_ignored() {
+        assert_eq!(Text::decode(b"spam"), None);
+        assert_eq!(Text::decode(&[0; 15]), None);
+    }
+
+    #[test]
+    fn test_edit_and_retract_roundtrip() {
+        let edit = Edit {
+            id: 7,
+            content: "fixed the typo".to_string(),
+        };
+        assert_eq!(Edit::decode(&edit.encode()), Some(edit));
+        assert_eq!(decode_retract(&encode_retract(7)), Some(7));
+        assert_eq!(decode_retract(&[0; 9]), None);
+        assert_eq!(Edit::decode(&[0; 7]), None);
+    }
+
+    #[test]
+    fn test_reaction_roundtrip() {
+        assert_eq!(
+            decode_reaction(&encode_reaction(9, "🎉")),
+            Some((9, "🎉".to_string()))
+        );
+        assert_eq!(decode_reaction(b"short"), None);
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        let ids: std::collections::HashSet<MessageId> = (0..1000).map(|_| new_id()).collect();
+        assert_eq!(ids.len(), 1000);
+        assert!(!ids.contains(&0));
+    }
+}
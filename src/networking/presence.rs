@@ -0,0 +1,83 @@
+use serde::Deserialize;
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    #[default]
+    Online = 0,
+    Away = 1,
+    Busy = 2,
+    Invisible = 3,
+}
+
+impl Presence {
+    fn from_u8(byte: u8) -> Option<Presence> {
+        match byte {
+            0 => Some(Presence::Online),
+            1 => Some(Presence::Away),
+            2 => Some(Presence::Busy),
+            3 => Some(Presence::Invisible),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Status {
+    pub presence: Presence,
+    pub text: String,
+}
+
+impl Status {
+    // Invisible users look offline to peers, so their status line stays private too
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![self.presence as u8];
+        if self.presence != Presence::Invisible {
+            buffer.extend(self.text.bytes());
+        }
+        buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Status> {
+        let (presence, text) = data.split_first()?;
+        Some(Status {
+            presence: Presence::from_u8(*presence)?,
+            text: String::from_utf8_lossy(text).to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_roundtrip() {
+        let status = Status {
+            presence: Presence::Busy,
+            text: "in a meeting".to_string(),
+        };
+        assert_eq!(Status::decode(&status.encode()), Some(status));
+    }
+
+    #[test]
+    fn test_invisible_status_hides_text() {
+        let status = Status {
+            presence: Presence::Invisible,
+            text: "secret".to_string(),
+        };
+        assert_eq!(
+            Status::decode(&status.encode()),
+            Some(Status {
+                presence: Presence::Invisible,
+                text: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_status_is_ignored() {
+        assert_eq!(Status::decode(&[]), None);
+        assert_eq!(Status::decode(&[9, b'x']), None);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+use super::sanitize;
+
+// A reaction is a single emoji or short piece of text, anything longer is not a reaction
+const MAX_REACTION_CHARS: usize = 8;
+// Reactions a peer can leave on one message, new ones past this are dropped
+const MAX_PEER_REACTIONS: usize = 32;
+
+// Offered by the picker, also what :shortcodes: expand to
+pub const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("tada", "🎉"),
+    ("eyes", "👀"),
+    ("check", "✅"),
+    ("thinking", "🤔"),
+    ("rocket", "🚀"),
+    ("pray", "🙏"),
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub by_self: bool,
+}
+
+// ":tada:" becomes "🎉", anything else is taken as typed once it's cleaned
+pub fn normalize(reaction: &str) -> Option<String> {
+    let reaction = reaction.trim();
+    let expanded = reaction
+        .strip_prefix(':')
+        .and_then(|r| r.strip_suffix(':'))
+        .and_then(|code| SHORTCODES.iter().find(|(name, _)| *name == code))
+        .map_or(reaction, |(_, emoji)| emoji);
+    let cleaned = sanitize::line(expanded);
+    (!cleaned.is_empty() && cleaned.chars().count() <= MAX_REACTION_CHARS).then_some(cleaned)
+}
+
+// Reacting twice with the same emoji takes the reaction back
+pub fn toggle(reactions: &mut Vec<Reaction>, emoji: &str, by_self: bool) {
+    let position = reactions
+        .iter()
+        .position(|r| r.emoji == emoji && r.by_self == by_self);
+    match position {
+        Some(index) => {
+            reactions.remove(index);
+        }
+        None if !by_self
+            && reactions.iter().filter(|r| !r.by_self).count() >= MAX_PEER_REACTIONS => {}
+        None => reactions.push(Reaction {
+            emoji: emoji.to_string(),
+            by_self,
+        }),
+    }
+}
+
+// Each emoji once with how many reacted with it and whether we did, in first-use order
+pub fn aggregate(reactions: &[Reaction]) -> Vec<(&str, usize, bool)> {
+    let mut counts: Vec<(&str, usize, bool)> = vec![];
+    for reaction in reactions {
+        match counts
+            .iter_mut()
+            .find(|(emoji, _, _)| *emoji == reaction.emoji)
+        {
+            Some((_, count, by_self)) => {
+                *count += 1;
+                *by_self |= reaction.by_self;
+            }
+            None => counts.push((&reaction.emoji, 1, reaction.by_self)),
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shortcodes_expand() {
+        assert_eq!(normalize(":tada:").as_deref(), Some("🎉"));
+        assert_eq!(normalize(" 👍 ").as_deref(), Some("👍"));
+        assert_eq!(normalize(":nope:").as_deref(), Some(":nope:"));
+    }
+
+    #[test]
+    fn test_hostile_reactions_are_rejected_or_cleaned() {
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize("a whole sentence instead"), None);
+        assert_eq!(normalize("\x1b[2J").as_deref(), Some("␛[2J"));
+    }
+
+    #[test]
+    fn test_toggle_and_aggregate() {
+        let mut reactions = vec![];
+        toggle(&mut reactions, "👍", true);
+        toggle(&mut reactions, "👍", false);
+        toggle(&mut reactions, "🎉", false);
+        assert_eq!(
+            aggregate(&reactions),
+            vec![("👍", 2, true), ("🎉", 1, false)]
+        );
+
+        toggle(&mut reactions, "👍", true);
+        toggle(&mut reactions, "🎉", false);
+        assert_eq!(aggregate(&reactions), vec![("👍", 1, false)]);
+    }
+
+    #[test]
+    fn test_peer_reactions_are_capped() {
+        let mut reactions = vec![];
+        for i in 0..MAX_PEER_REACTIONS + 5 {
+            toggle(&mut reactions, &i.to_string(), false);
+        }
+        assert_eq!(reactions.len(), MAX_PEER_REACTIONS);
+        // Taking one back still works and ours are never held back
+        toggle(&mut reactions, "0", false);
+        toggle(&mut reactions, "👍", true);
+        assert_eq!(reactions.len(), MAX_PEER_REACTIONS);
+        assert!(reactions.iter().any(|r| r.by_self));
+    }
+}
@@ -0,0 +1,140 @@
+// Everything a peer sends ends up on our terminal, so it's cleaned before it's stored.
+// Control characters are swapped for their visible Control Pictures, which also defuses
+// escape sequences: "\x1b[2J" shows up as "␛[2J" instead of clearing the screen.
+// Bidi controls can make text read differently than it is stored, so they are replaced
+// by a marker naming them rather than silently dropped.
+
+const TAB: &str = "    ";
+
+// Message bodies, which may span several lines
+pub fn content(text: &str) -> String {
+    clean(text, true)
+}
+
+// Names and status lines, which have to fit on one line
+pub fn line(text: &str) -> String {
+    clean(text, false)
+}
+
+fn clean(text: &str, multiline: bool) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\u{2028}' | '\u{2029}' if multiline => cleaned.push('\n'),
+            '\n' | '\u{2028}' | '\u{2029}' => cleaned.push(' '),
+            '\r' if multiline => {}
+            '\t' => cleaned.push_str(TAB),
+            // C0 controls and DEL have a picture each
+            '\0'..='\u{1f}' => cleaned.push(char::from_u32(0x2400 + c as u32).unwrap()),
+            '\u{7f}' => cleaned.push('␡'),
+            // C1 controls include a single byte CSI, none of them are printable
+            '\u{80}'..='\u{9f}' => cleaned.push(char::REPLACEMENT_CHARACTER),
+            _ => match bidi_name(c) {
+                Some(name) => {
+                    cleaned.push('⟦');
+                    cleaned.push_str(name);
+                    cleaned.push('⟧');
+                }
+                None if is_invisible(c) => {}
+                None => cleaned.push(c),
+            },
+        }
+    }
+    cleaned
+}
+
+fn bidi_name(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{061c}' => "ALM",
+        '\u{200e}' => "LRM",
+        '\u{200f}' => "RLM",
+        '\u{202a}' => "LRE",
+        '\u{202b}' => "RLE",
+        '\u{202c}' => "PDF",
+        '\u{202d}' => "LRO",
+        '\u{202e}' => "RLO",
+        '\u{2066}' => "LRI",
+        '\u{2067}' => "RLI",
+        '\u{2068}' => "FSI",
+        '\u{2069}' => "PDI",
+        _ => return None,
+    })
+}
+
+// Zero width characters that can hide text or make two names look the same. Joiners stay,
+// emoji sequences and several scripts need them
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200b}' | '\u{2060}'..='\u{2064}' | '\u{feff}' | '\u{00ad}' | '\u{180e}'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_is_untouched() {
+        let text = "héllo wörld 👋\nsecond line — with ünïcode, 日本語";
+        assert_eq!(content(text), text);
+        assert_eq!(line("Nikolas"), "Nikolas");
+    }
+
+    #[test]
+    fn test_escape_sequences_are_neutralised() {
+        for hostile in [
+            "\x1b[2J\x1b[H",
+            "\x1b]0;owned\x07",
+            "\x1b]52;c;ZXZpbA==\x07",
+            "\x1b]8;;https://evil.example\x1b\\click\x1b]8;;\x1b\\",
+            "\x1bP+q544e\x1b\\",
+            "\u{9b}31m",
+            "\x1b[6n",
+        ] {
+            let cleaned = content(hostile);
+            assert!(
+                !cleaned.chars().any(|c| c.is_control() && c != '\n'),
+                "{:?} became {:?}",
+                hostile,
+                cleaned
+            );
+        }
+        assert_eq!(content("\x1b[31mred"), "␛[31mred");
+        assert_eq!(content("ring\x07"), "ring␇");
+    }
+
+    #[test]
+    fn test_carriage_returns_cannot_overwrite_lines() {
+        assert_eq!(content("harmless\rrm -rf /"), "harmlessrm -rf /");
+        assert_eq!(content("a\r\nb"), "a\nb");
+        assert_eq!(line("a\rb"), "a␍b");
+    }
+
+    #[test]
+    fn test_bidi_overrides_are_flagged() {
+        // Reads as "invoice_fdp.exe" once rendered
+        assert_eq!(content("invoice_\u{202e}exe.pdf"), "invoice_⟦RLO⟧exe.pdf");
+        assert_eq!(line("\u{2067}admin\u{2069}"), "⟦RLI⟧admin⟦PDI⟧");
+        assert_eq!(content("\u{200f}"), "⟦RLM⟧");
+    }
+
+    #[test]
+    fn test_zero_width_characters_are_removed() {
+        assert_eq!(line("Ni\u{200b}ko\u{feff}las\u{2060}"), "Nikolas");
+        assert_eq!(content("soft\u{ad}hyphen"), "softhyphen");
+        // Joiners hold emoji sequences together
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(content(family), family);
+    }
+
+    #[test]
+    fn test_names_stay_on_one_line() {
+        assert_eq!(
+            line("Mallory\nSystem: you have been kicked"),
+            "Mallory System: you have been kicked"
+        );
+        assert_eq!(line("a\u{2028}b\tc"), "a b    c");
+        assert_eq!(content("a\u{2029}b"), "a\nb");
+    }
+}
@@ -0,0 +1,561 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Days, FixedOffset, Local, NaiveDate, NaiveTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    keymap::Keymap,
+    networking::{access::AccessList, limits::RateLimits, mentions::Mentions, notify::NotifyLevel},
+    theme::{self, ColorDepth},
+};
+
+const CONFIG_ENV: &str = "TUI_CHAT_CONFIG";
+const APP_DIR: &str = "tui_chat";
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // Display name sent to peers, also counts as a mention
+    pub name: Option<String>,
+    pub access: AccessList,
+    pub limits: RateLimits,
+    pub presence: PresenceSettings,
+    pub connections: ConnectionListSettings,
+    pub mentions: Mentions,
+    pub notifications: NotificationSettings,
+    pub links: LinkSettings,
+    pub clipboard: ClipboardSettings,
+    pub input: InputSettings,
+    pub keys: Keymap,
+    pub theme: ThemeSettings,
+    pub time: TimeSettings,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DesktopNotification {
+    #[default]
+    Off,
+    Osc9,
+    Osc777,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationSettings {
+    pub bell: bool,
+    pub desktop: DesktopNotification,
+    pub window_title: bool,
+    pub default_level: NotifyLevel,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            bell: true,
+            desktop: DesktopNotification::Off,
+            window_title: true,
+            default_level: NotifyLevel::All,
+            quiet_hours: None,
+        }
+    }
+}
+
+// Written as "22:00-07:00", a range that ends before it starts wraps past midnight
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(range: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid quiet hours '{}', expected HH:MM-HH:MM", range);
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+        let parse =
+            |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid());
+        Ok(QuietHours {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputSettings {
+    // Pastes with more lines ask before landing in the input, zero never asks
+    pub paste_threshold: usize,
+    // Capturing the mouse takes over the terminal's own text selection
+    pub mouse: bool,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            paste_threshold: 20,
+            mouse: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardMethod {
+    // Through the terminal, works over ssh but not every terminal allows it
+    #[default]
+    Osc52,
+    Command,
+    File,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardSettings {
+    pub method: ClipboardMethod,
+    // Gets the copied text on stdin
+    pub command: String,
+    pub file: PathBuf,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        Self {
+            method: ClipboardMethod::Osc52,
+            command: if cfg!(target_os = "macos") {
+                "pbcopy"
+            } else {
+                "wl-copy"
+            }
+            .to_string(),
+            file: Settings::config_dir().join("clipboard.txt"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkSettings {
+    // Turn off for terminals that print OSC 8 sequences instead of understanding them
+    pub hyperlinks: bool,
+    // Program used by the link picker, the url is appended as the last argument
+    pub open_command: String,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            hyperlinks: true,
+            open_command: if cfg!(target_os = "macos") {
+                "open"
+            } else {
+                "xdg-open"
+            }
+            .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeSettings {
+    // A built-in theme or the name of a file in the themes directory
+    pub name: String,
+    // Colours the theme uses that the terminal can't show are swapped for the nearest it can
+    pub colors: ColorDepth,
+}
+
+impl Default for ThemeSettings {
+    fn default() -> Self {
+        Self {
+            name: theme::DEFAULT_THEME.to_string(),
+            colors: ColorDepth::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeSettings {
+    // strftime style, for the time in front of every message
+    pub format: TimeFormat,
+    // For the separators between days other than today and yesterday
+    pub date_format: DateFormat,
+    pub timezone: Zone,
+    // "5m ago" for anything from the last day, the format after that
+    pub relative: bool,
+    // Follow-ups from the same sender within this many minutes share its header, zero never
+    pub group_minutes: u64,
+}
+
+impl Default for TimeSettings {
+    fn default() -> Self {
+        Self {
+            format: TimeFormat("%H:%M:%S".to_string()),
+            date_format: DateFormat("%A, %-d %B %Y".to_string()),
+            timezone: Zone::Local,
+            relative: false,
+            group_minutes: 5,
+        }
+    }
+}
+
+impl TimeSettings {
+    pub fn time(&self, time: SystemTime, now: SystemTime) -> String {
+        let elapsed = now.duration_since(time).unwrap_or_default().as_secs();
+        match elapsed {
+            _ if !self.relative => self.timezone.at(time).format(&self.format.0).to_string(),
+            0..60 => "just now".to_string(),
+            60..3600 => format!("{}m ago", elapsed / 60),
+            3600..86400 => format!("{}h ago", elapsed / 3600),
+            _ => self.timezone.at(time).format(&self.format.0).to_string(),
+        }
+    }
+
+    pub fn date(&self, time: SystemTime) -> NaiveDate {
+        self.timezone.at(time).date_naive()
+    }
+
+    pub fn date_label(&self, date: NaiveDate, now: SystemTime) -> String {
+        let today = self.date(now);
+        if date == today {
+            "Today".to_string()
+        } else if Some(date) == today.checked_sub_days(Days::new(1)) {
+            "Yesterday".to_string()
+        } else {
+            date.format(&self.date_format.0).to_string()
+        }
+    }
+
+    // Whether a message goes under the previous one's header
+    pub fn same_group(&self, previous: SystemTime, time: SystemTime) -> bool {
+        self.group_minutes > 0
+            && time
+                .duration_since(previous)
+                .is_ok_and(|gap| gap.as_secs() < self.group_minutes * 60)
+            && self.date(previous) == self.date(time)
+    }
+}
+
+// Checked up front by formatting a sample, chrono panics on a bad format only once it's
+// displayed. That includes valid specifiers the value can't fill, like %H for a date
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeFormat(String);
+
+impl TryFrom<String> for TimeFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        let sample = DateTime::UNIX_EPOCH.fixed_offset();
+        match try_format(sample.format(&format)) {
+            Ok(()) => Ok(TimeFormat(format)),
+            Err(_) => Err(format!("invalid time format '{}'", format)),
+        }
+    }
+}
+
+// For whole days, so only date specifiers are allowed
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DateFormat(String);
+
+impl TryFrom<String> for DateFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        let sample = DateTime::UNIX_EPOCH.date_naive();
+        match try_format(sample.format(&format)) {
+            Ok(()) => Ok(DateFormat(format)),
+            Err(_) => Err(format!("invalid date format '{}'", format)),
+        }
+    }
+}
+
+fn try_format(formatted: impl fmt::Display) -> fmt::Result {
+    write!(String::new(), "{}", formatted)
+}
+
+// "local", "utc" or a fixed offset like "+05:30"
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Zone {
+    Local,
+    Utc,
+    Offset(FixedOffset),
+}
+
+impl Zone {
+    pub fn at(&self, time: SystemTime) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Local => DateTime::<Local>::from(time).fixed_offset(),
+            Zone::Utc => DateTime::<Utc>::from(time).fixed_offset(),
+            Zone::Offset(offset) => DateTime::<Utc>::from(time).with_timezone(offset),
+        }
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(zone: String) -> Result<Self, Self::Error> {
+        match zone.to_lowercase().as_str() {
+            "local" => Ok(Zone::Local),
+            "utc" => Ok(Zone::Utc),
+            offset => offset.parse().map(Zone::Offset).map_err(|_| {
+                format!(
+                    "invalid timezone '{}', expected local, utc or an offset like +05:30",
+                    zone
+                )
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionListSettings {
+    pub sort_by_activity: bool,
+    // Our own names for peers, keyed by "ip" or "ip:port", for finding them in the switcher
+    pub aliases: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresenceSettings {
+    // Zero turns auto-away off
+    pub auto_away_minutes: u64,
+    pub status: String,
+}
+
+impl Default for PresenceSettings {
+    fn default() -> Self {
+        Self {
+            auto_away_minutes: 10,
+            status: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    // Missing config file is not an error, we just run with the defaults
+    pub fn load() -> io::Result<Settings> {
+        let path = Settings::config_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => Settings::parse(&content, &path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn parse(content: &str, path: &Path) -> io::Result<Settings> {
+        toml::from_str(content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    pub fn config_path() -> PathBuf {
+        if let Some(path) = env::var_os(CONFIG_ENV) {
+            return PathBuf::from(path);
+        }
+        Settings::config_dir().join(CONFIG_FILE)
+    }
+
+    pub fn themes_dir() -> PathBuf {
+        Settings::config_dir().join("themes")
+    }
+
+    pub fn identity_path() -> PathBuf {
+        Settings::config_dir().join("identity")
+    }
+
+    pub fn config_dir() -> PathBuf {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_else(|| PathBuf::from("."));
+        base.join(APP_DIR)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::keymap::Mode;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_quiet_hours_within_a_day() {
+        let quiet = QuietHours::try_from("12:30-14:00".to_string()).unwrap();
+        assert!(quiet.contains(time(12, 30)));
+        assert!(quiet.contains(time(13, 59)));
+        assert!(!quiet.contains(time(14, 0)));
+        assert!(!quiet.contains(time(8, 0)));
+    }
+
+    #[test]
+    fn test_quiet_hours_over_midnight() {
+        let quiet = QuietHours::try_from("22:00-07:00".to_string()).unwrap();
+        assert!(quiet.contains(time(23, 15)));
+        assert!(quiet.contains(time(3, 0)));
+        assert!(!quiet.contains(time(7, 0)));
+        assert!(!quiet.contains(time(12, 0)));
+    }
+
+    #[test]
+    fn test_time_in_a_fixed_zone() {
+        let settings = TimeSettings {
+            format: TimeFormat::try_from("%d %H:%M".to_string()).unwrap(),
+            timezone: Zone::try_from("+05:30".to_string()).unwrap(),
+            ..TimeSettings::default()
+        };
+        // 23:00 UTC on the 1st is 04:30 on the 2nd
+        let late = at(23 * 3600);
+        assert_eq!(settings.time(late, late), "02 04:30");
+        assert_eq!(
+            settings.date(late),
+            NaiveDate::from_ymd_opt(1970, 1, 2).unwrap()
+        );
+        assert_eq!(settings.date_label(settings.date(late), late), "Today");
+        assert_eq!(
+            settings.date_label(settings.date(at(0)), at(2 * 86400)),
+            "Thursday, 1 January 1970"
+        );
+        // Across midnight over there, so no shared header
+        assert!(!settings.same_group(at(18 * 3600 + 1770), at(18 * 3600 + 1830)));
+        assert!(settings.same_group(at(17 * 3600), at(17 * 3600 + 60)));
+        assert!(!settings.same_group(at(0), at(600)));
+    }
+
+    #[test]
+    fn test_relative_times() {
+        let settings = TimeSettings {
+            relative: true,
+            timezone: Zone::Utc,
+            ..TimeSettings::default()
+        };
+        let now = at(86400 * 3);
+        assert_eq!(settings.time(now, now), "just now");
+        assert_eq!(settings.time(at(86400 * 3 - 150), now), "2m ago");
+        assert_eq!(settings.time(at(86400 * 2 + 60), now), "23h ago");
+        assert_eq!(settings.time(at(86400 + 5), now), "00:00:05");
+    }
+
+    #[test]
+    fn test_settings_parse() {
+        let settings = Settings::parse(
+            r#"
+            [notifications]
+            desktop = "osc777"
+            quiet_hours = "22:00-07:00"
+            default_level = "mentions"
+
+            [mentions]
+            keywords = ["oncall"]
+
+            [links]
+            open_command = "firefox --new-tab"
+
+            [clipboard]
+            method = "command"
+            command = "xclip -selection clipboard"
+
+            [input]
+            mouse = false
+
+            [keys]
+            preset = "emacs"
+            normal = { "ctrl+x" = "quit" }
+
+            [theme]
+            name = "light"
+            colors = "256"
+
+            [connections.aliases]
+            "10.0.0.7" = "Alice at work"
+
+            [time]
+            format = "%H:%M"
+            timezone = "UTC"
+            relative = true
+            "#,
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        assert_eq!(settings.notifications.desktop, DesktopNotification::Osc777);
+        assert_eq!(settings.notifications.default_level, NotifyLevel::Mentions);
+        assert!(settings.notifications.quiet_hours.is_some());
+        assert_eq!(settings.mentions.keywords, vec!["oncall"]);
+        assert_eq!(settings.links.open_command, "firefox --new-tab");
+        assert!(settings.links.hyperlinks);
+        assert_eq!(settings.clipboard.method, ClipboardMethod::Command);
+        assert_eq!(settings.clipboard.command, "xclip -selection clipboard");
+        assert!(!settings.input.mouse);
+        assert_eq!(settings.input.paste_threshold, 20);
+        assert_eq!(settings.theme.name, "light");
+        assert_eq!(settings.theme.colors, ColorDepth::Indexed);
+        assert_eq!(settings.time.timezone, Zone::Utc);
+        assert_eq!(settings.time.format, TimeFormat("%H:%M".to_string()));
+        assert_eq!(settings.time.group_minutes, 5);
+        assert_eq!(settings.connections.aliases["10.0.0.7"], "Alice at work");
+        assert!(settings
+            .keys
+            .help(Mode::Normal)
+            .contains(&("ctrl+c ctrl+x".to_string(), "quit")));
+
+        assert!(Settings::parse("quiet = 1", Path::new("config.toml")).is_err());
+        assert!(Settings::parse(
+            "[notifications]\nquiet_hours = \"late\"",
+            Path::new("config.toml")
+        )
+        .is_err());
+        assert!(Settings::parse("[time]\nformat = \"%Q\"", Path::new("config.toml")).is_err());
+        assert!(Settings::parse(
+            "[time]\ndate_format = \"%d %H:%M\"",
+            Path::new("config.toml")
+        )
+        .is_err());
+        assert!(
+            Settings::parse("[time]\ndate_format = \"%d %B\"", Path::new("config.toml")).is_ok()
+        );
+        assert!(Settings::parse(
+            "[time]\ntimezone = \"Mars/Olympus\"",
+            Path::new("config.toml")
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,626 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+// Every style the interface draws with. Themes are written against these names, a theme
+// that names anything else is rejected so typos don't go unnoticed
+pub const STYLES: &[&str] = &[
+    "list.unselected",
+    "list.selected",
+    "list.highlight",
+    "list.status",
+    "list.unread",
+    "list.unread_badge",
+    "list.mention_badge",
+    "presence.online",
+    "presence.away",
+    "presence.busy",
+    "presence.invisible",
+    "message.username.self",
+    "message.username.peer",
+    "message.time",
+    "message.time.focused",
+    "message.search_match",
+    "message.text",
+    "message.error",
+    "message.typing",
+    "message.unread_marker",
+    "message.date_separator",
+    "message.mention",
+    "message.reaction.self",
+    "message.reaction.peer",
+    "message.tombstone",
+    "message.link",
+    "message.quote",
+    "message.list_bullet",
+    "message.focused_pane",
+    "code.inline",
+    "code.block",
+    "code.comment",
+    "code.string",
+    "code.constant",
+    "code.keyword",
+    "code.function",
+    "code.type",
+    "input.unselected",
+    "input.selected",
+    "status.bar",
+    "status.state",
+    "status.key",
+    "status.unencrypted",
+    "status.heading",
+    "popup",
+];
+
+// Only the sixteen ANSI colours, which the terminal's own palette decides the look of.
+// The other built-in themes start from this one
+const SIXTEEN_COLORS: &str = r##"
+[styles]
+"list.unselected" = "gray"
+"list.selected" = "yellow"
+"list.highlight" = "bold italic"
+"list.status" = "darkgray italic"
+"list.unread" = "bold"
+"list.unread_badge" = "lightcyan bold"
+"list.mention_badge" = "lightmagenta bold"
+"presence.online" = "green"
+"presence.away" = "yellow"
+"presence.busy" = "red"
+"presence.invisible" = "darkgray"
+"message.username.self" = "lightyellow bold"
+"message.username.peer" = "yellow bold"
+"message.time" = "gray italic"
+"message.time.focused" = "black on gray"
+"message.search_match" = "black on yellow"
+"message.text" = "white"
+"message.error" = "red italic"
+"message.typing" = "gray italic"
+"message.unread_marker" = "lightcyan"
+"message.date_separator" = "darkgray bold"
+"message.mention" = "lightmagenta bold"
+"message.reaction.self" = "black on lightyellow"
+"message.reaction.peer" = "white on darkgray"
+"message.tombstone" = "darkgray italic"
+"message.link" = "lightblue underlined"
+"message.quote" = "gray italic"
+"message.list_bullet" = "yellow"
+"message.focused_pane" = "yellow bold"
+"code.inline" = "lightgreen on black"
+"code.block" = "white on black"
+"code.comment" = "darkgray on black italic"
+"code.string" = "green on black"
+"code.constant" = "lightred on black"
+"code.keyword" = "magenta on black"
+"code.function" = "lightblue on black"
+"code.type" = "yellow on black"
+"input.unselected" = "gray"
+"input.selected" = "yellow"
+"status.bar" = "gray on darkgray"
+"status.state" = "black on yellow bold"
+"status.key" = "yellow bold"
+"status.unencrypted" = "lightred"
+"status.heading" = "yellow bold underlined"
+"popup" = "on black"
+"##;
+
+const DARK: &str = r##"
+base = "16color"
+
+[styles]
+"list.selected" = "#e5c07b"
+"list.unread_badge" = "#56b6c2 bold"
+"list.mention_badge" = "#c678dd bold"
+"presence.online" = "#98c379"
+"presence.away" = "#e5c07b"
+"presence.busy" = "#e06c75"
+"message.username.self" = "#f0d48a bold"
+"message.username.peer" = "#e5c07b bold"
+"message.time" = "#7f848e italic"
+"message.time.focused" = "#282c34 on #abb2bf"
+"message.search_match" = "#282c34 on #e5c07b"
+"message.text" = "#dcdfe4"
+"message.error" = "#e06c75 italic"
+"message.unread_marker" = "#56b6c2"
+"message.date_separator" = "#7f848e bold"
+"message.mention" = "#c678dd bold"
+"message.reaction.self" = "#282c34 on #f0d48a"
+"message.reaction.peer" = "#dcdfe4 on #3e4451"
+"message.link" = "#61afef underlined"
+"message.list_bullet" = "#e5c07b"
+"message.focused_pane" = "#e5c07b bold"
+"code.inline" = "#98c379 on #2c313a"
+"code.block" = "#dcdfe4 on #2c313a"
+"code.comment" = "#7f848e on #2c313a italic"
+"code.string" = "#98c379 on #2c313a"
+"code.constant" = "#d19a66 on #2c313a"
+"code.keyword" = "#c678dd on #2c313a"
+"code.function" = "#61afef on #2c313a"
+"code.type" = "#e5c07b on #2c313a"
+"input.selected" = "#e5c07b"
+"status.bar" = "#abb2bf on #3e4451"
+"status.state" = "#282c34 on #e5c07b bold"
+"status.key" = "#e5c07b bold"
+"status.unencrypted" = "#e06c75"
+"status.heading" = "#e5c07b bold underlined"
+"popup" = "on #21252b"
+"##;
+
+// For terminals with a light background
+const LIGHT: &str = r##"
+base = "16color"
+
+[styles]
+"list.unselected" = "darkgray"
+"list.selected" = "blue"
+"list.status" = "gray italic"
+"list.unread_badge" = "cyan bold"
+"list.mention_badge" = "magenta bold"
+"presence.away" = "#9a6700"
+"presence.invisible" = "gray"
+"message.username.self" = "blue bold"
+"message.username.peer" = "magenta bold"
+"message.time" = "darkgray italic"
+"message.time.focused" = "white on darkgray"
+"message.search_match" = "black on #ffd75f"
+"message.text" = "black"
+"message.typing" = "darkgray italic"
+"message.unread_marker" = "cyan"
+"message.date_separator" = "gray bold"
+"message.mention" = "magenta bold"
+"message.reaction.self" = "white on blue"
+"message.reaction.peer" = "black on #dddddd"
+"message.tombstone" = "gray italic"
+"message.link" = "blue underlined"
+"message.quote" = "darkgray italic"
+"message.list_bullet" = "blue"
+"message.focused_pane" = "blue bold"
+"code.inline" = "#1a7f37 on #eeeeee"
+"code.block" = "black on #eeeeee"
+"code.comment" = "#6e7781 on #eeeeee italic"
+"code.string" = "#0a3069 on #eeeeee"
+"code.constant" = "#953800 on #eeeeee"
+"code.keyword" = "#cf222e on #eeeeee"
+"code.function" = "#8250df on #eeeeee"
+"code.type" = "#0550ae on #eeeeee"
+"input.unselected" = "darkgray"
+"input.selected" = "blue"
+"status.bar" = "black on #dddddd"
+"status.state" = "white on blue bold"
+"status.key" = "blue bold"
+"status.unencrypted" = "red"
+"status.heading" = "blue bold underlined"
+"popup" = "black on white"
+"##;
+
+// Bright on black, no dim greys, and bold wherever something needs to stand out
+const HIGH_CONTRAST: &str = r##"
+base = "16color"
+
+[styles]
+"list.unselected" = "white"
+"list.selected" = "lightyellow bold"
+"list.highlight" = "black on white bold"
+"list.status" = "white italic"
+"presence.invisible" = "white"
+"message.time" = "white"
+"message.time.focused" = "black on white bold"
+"message.search_match" = "black on lightyellow bold"
+"message.error" = "lightred bold"
+"message.typing" = "white italic"
+"message.reaction.peer" = "black on white"
+"message.tombstone" = "white italic"
+"message.link" = "lightcyan bold underlined"
+"message.quote" = "white italic"
+"message.focused_pane" = "lightyellow bold"
+"message.date_separator" = "white bold"
+"code.comment" = "white on black italic"
+"code.keyword" = "lightmagenta on black bold"
+"code.string" = "lightgreen on black"
+"input.unselected" = "white"
+"input.selected" = "lightyellow bold"
+"status.bar" = "white on black"
+"status.state" = "black on white bold"
+"status.key" = "lightyellow bold"
+"status.heading" = "lightyellow bold underlined"
+"popup" = "white on black"
+"##;
+
+const BUILTIN: &[(&str, &str)] = &[
+    ("dark", DARK),
+    ("light", LIGHT),
+    ("high-contrast", HIGH_CONTRAST),
+    ("16color", SIXTEEN_COLORS),
+];
+
+// Themes that say nothing about their base start from this one
+pub const DEFAULT_THEME: &str = "dark";
+// Deep enough for any sensible chain, and stops a theme that is its own base
+const MAX_BASES: usize = 8;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorDepth {
+    // Decided from COLORTERM and TERM
+    #[default]
+    Auto,
+    Truecolor,
+    #[serde(rename = "256")]
+    Indexed,
+    #[serde(rename = "16")]
+    Basic,
+}
+
+impl ColorDepth {
+    // Terminals that can do 24 bit colour say so in COLORTERM, most others at least
+    // mention 256 colours in TERM
+    pub fn detect(self) -> ColorDepth {
+        if self != ColorDepth::Auto {
+            return self;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::Truecolor
+        } else if term.contains("256") {
+            ColorDepth::Indexed
+        } else {
+            ColorDepth::Basic
+        }
+    }
+
+    fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Indexed, Color::Rgb(r, g, b)) => indexed(r, g, b),
+            (ColorDepth::Basic, Color::Rgb(r, g, b)) => basic(r, g, b),
+            _ => color,
+        }
+    }
+}
+
+// Nearest entry of the 6x6x6 cube or the grey ramp of the 256 colour palette
+fn indexed(r: u8, g: u8, b: u8) -> Color {
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    if r == g && g == b {
+        return match r {
+            0..=7 => Color::Indexed(16),
+            // The ramp stops at 238, past its last step white is closer
+            248..=255 => Color::Indexed(231),
+            _ => Color::Indexed(232 + (r - 8) / 10),
+        };
+    }
+    Color::Indexed(16 + 36 * level(r) + 6 * level(g) + level(b))
+}
+
+// Nearest of the ANSI colours, going by the usual xterm palette
+fn basic(r: u8, g: u8, b: u8) -> Color {
+    const PALETTE: &[(Color, (u8, u8, u8))] = &[
+        (Color::Black, (0, 0, 0)),
+        (Color::Red, (205, 0, 0)),
+        (Color::Green, (0, 205, 0)),
+        (Color::Yellow, (205, 205, 0)),
+        (Color::Blue, (0, 0, 238)),
+        (Color::Magenta, (205, 0, 205)),
+        (Color::Cyan, (0, 205, 205)),
+        (Color::Gray, (229, 229, 229)),
+        (Color::DarkGray, (127, 127, 127)),
+        (Color::LightRed, (255, 0, 0)),
+        (Color::LightGreen, (0, 255, 0)),
+        (Color::LightYellow, (255, 255, 0)),
+        (Color::LightBlue, (92, 92, 255)),
+        (Color::LightMagenta, (255, 0, 255)),
+        (Color::LightCyan, (0, 255, 255)),
+        (Color::White, (255, 255, 255)),
+    ];
+    let distance = |(pr, pg, pb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, pr) + d(g, pg) + d(b, pb)
+    };
+    PALETTE
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(color, _)| *color)
+        .unwrap()
+}
+
+// "lightgreen on black bold": a foreground, a background after "on", then modifiers.
+// Colours are names, 256 colour indexes or #rrggbb
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+struct StyleSpec(Style);
+
+impl TryFrom<String> for StyleSpec {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        let mut style = Style::new();
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            let modifier = match word.to_lowercase().as_str() {
+                "bold" => Some(Modifier::BOLD),
+                "italic" => Some(Modifier::ITALIC),
+                "underlined" => Some(Modifier::UNDERLINED),
+                "dim" => Some(Modifier::DIM),
+                "reversed" => Some(Modifier::REVERSED),
+                "crossed_out" => Some(Modifier::CROSSED_OUT),
+                _ => None,
+            };
+            let color = |word: Option<&str>| {
+                word.and_then(|w| Color::from_str(w).ok())
+                    .ok_or_else(|| format!("invalid style '{}'", spec))
+            };
+            style = match (word, modifier) {
+                (_, Some(modifier)) => style.add_modifier(modifier),
+                ("on", None) => style.bg(color(words.next())?),
+                (word, None) if style.fg.is_none() => style.fg(color(Some(word))?),
+                _ => return Err(format!("invalid style '{}'", spec)),
+            };
+        }
+        Ok(StyleSpec(style))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    #[serde(default)]
+    styles: HashMap<String, StyleSpec>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    styles: HashMap<String, Style>,
+}
+
+impl Theme {
+    pub fn style(&self, name: &str) -> Style {
+        self.styles.get(name).copied().unwrap_or_default()
+    }
+}
+
+// Built-in themes plus any <name>.toml in the themes directory, which win on a name clash
+pub struct Themes {
+    dir: PathBuf,
+    depth: ColorDepth,
+}
+
+impl Themes {
+    pub fn new(dir: PathBuf, depth: ColorDepth) -> Self {
+        Self {
+            dir,
+            depth: depth.detect(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN.iter().map(|(name, _)| name.to_string()).collect();
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "toml") {
+                    if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                        if !names.iter().any(|n| n == name) {
+                            names.push(name.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    pub fn load(&self, name: &str) -> Result<Theme, String> {
+        let mut styles = HashMap::new();
+        self.resolve(name, &mut styles, 0, true)?;
+        for style in styles.values_mut() {
+            style.fg = style.fg.map(|c| self.depth.convert(c));
+            style.bg = style.bg.map(|c| self.depth.convert(c));
+        }
+        Ok(Theme {
+            name: name.to_string(),
+            styles,
+        })
+    }
+
+    // Bases are applied first so the theme's own styles land on top
+    fn resolve(
+        &self,
+        name: &str,
+        styles: &mut HashMap<String, Style>,
+        depth: usize,
+        files: bool,
+    ) -> Result<(), String> {
+        if depth > MAX_BASES {
+            return Err(format!("theme '{}' has too many bases", name));
+        }
+        // A name is a file stem in the themes directory, never a path out of it
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(format!("invalid theme name '{}'", name));
+        }
+        let path = self.dir.join(format!("{}.toml", name));
+        let file = files.then(|| fs::read_to_string(&path).ok()).flatten();
+        let (source, builtin) = match file {
+            Some(source) => (source, false),
+            None => match BUILTIN.iter().find(|(n, _)| *n == name) {
+                Some((_, source)) => (source.to_string(), true),
+                None => return Err(format!("no theme named '{}'", name)),
+            },
+        };
+        let file: ThemeFile = toml::from_str(&source).map_err(|e| {
+            if builtin {
+                format!("theme '{}': {}", name, e)
+            } else {
+                format!("{}: {}", path.display(), e)
+            }
+        })?;
+        if let Some(unknown) = file.styles.keys().find(|k| !STYLES.contains(&k.as_str())) {
+            return Err(format!("theme '{}': unknown style '{}'", name, unknown));
+        }
+        // A theme file that doesn't name a base builds on the built-in it shares a name
+        // with, or else on the default theme
+        let base = match file.base {
+            Some(base) => Some(base),
+            None if !builtin && BUILTIN.iter().any(|(n, _)| *n == name) => Some(name.to_string()),
+            None if !builtin => Some(DEFAULT_THEME.to_string()),
+            None => None,
+        };
+        if let Some(base) = base {
+            // A file named like a built-in builds on that built-in, not on itself
+            let files = builtin || base != name;
+            self.resolve(&base, styles, depth + 1, files)?;
+        }
+        styles.extend(file.styles.into_iter().map(|(k, v)| (k, v.0)));
+        Ok(())
+    }
+}
+
+// The theme in use, read by every style function while drawing
+static ACTIVE: RwLock<Option<Arc<Theme>>> = RwLock::new(None);
+
+pub fn set(theme: Theme) {
+    *ACTIVE.write().unwrap() = Some(Arc::new(theme));
+}
+
+pub fn current_name() -> Option<String> {
+    ACTIVE.read().unwrap().as_ref().map(|t| t.name.clone())
+}
+
+pub fn style(name: &str) -> Style {
+    ACTIVE
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|theme| theme.style(name))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(s: &str) -> Result<Style, String> {
+        StyleSpec::try_from(s.to_string()).map(|s| s.0)
+    }
+
+    fn themes(depth: ColorDepth) -> Themes {
+        Themes::new(PathBuf::from("/nonexistent"), depth)
+    }
+
+    #[test]
+    fn test_style_specs() {
+        assert_eq!(
+            spec("lightgreen on black bold"),
+            Ok(Style::new()
+                .fg(Color::LightGreen)
+                .bg(Color::Black)
+                .add_modifier(Modifier::BOLD))
+        );
+        assert_eq!(
+            spec("on #21252b"),
+            Ok(Style::new().bg(Color::Rgb(0x21, 0x25, 0x2b)))
+        );
+        assert_eq!(
+            spec("bold italic"),
+            Ok(Style::new().add_modifier(Modifier::BOLD | Modifier::ITALIC))
+        );
+        assert_eq!(spec("242"), Ok(Style::new().fg(Color::Indexed(242))));
+        assert!(spec("red blue").is_err());
+        assert!(spec("on").is_err());
+        assert!(spec("sparkly").is_err());
+    }
+
+    #[test]
+    fn test_builtin_themes_define_every_style() {
+        let themes = themes(ColorDepth::Truecolor);
+        for (name, _) in BUILTIN {
+            let theme = themes.load(name).unwrap();
+            for style in STYLES {
+                assert!(
+                    theme.styles.contains_key(*style),
+                    "{} lacks {}",
+                    name,
+                    style
+                );
+            }
+        }
+        assert!(themes.load("neon").is_err());
+    }
+
+    #[test]
+    fn test_nearest_indexed_colour() {
+        assert_eq!(indexed(0, 0, 0), Color::Indexed(16));
+        assert_eq!(indexed(18, 18, 18), Color::Indexed(233));
+        assert_eq!(indexed(238, 238, 238), Color::Indexed(255));
+        for grey in 239..=255 {
+            assert!(matches!(
+                indexed(grey, grey, grey),
+                Color::Indexed(231..=255)
+            ));
+        }
+        assert_eq!(indexed(248, 248, 248), Color::Indexed(231));
+        assert_eq!(indexed(255, 0, 0), Color::Indexed(196));
+    }
+
+    #[test]
+    fn test_fallback_without_truecolor() {
+        let truecolor = themes(ColorDepth::Truecolor).load("dark").unwrap();
+        assert_eq!(
+            truecolor.style("popup").bg,
+            Some(Color::Rgb(0x21, 0x25, 0x2b))
+        );
+
+        let indexed = themes(ColorDepth::Indexed).load("dark").unwrap();
+        assert!(matches!(indexed.style("popup").bg, Some(Color::Indexed(_))));
+
+        let basic = themes(ColorDepth::Basic).load("dark").unwrap();
+        assert_eq!(basic.style("popup").bg, Some(Color::Black));
+        assert_eq!(basic.style("message.link").fg, Some(Color::LightBlue));
+    }
+
+    #[test]
+    fn test_theme_files() {
+        let dir = env::temp_dir().join(format!("tui_chat_themes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("solar.toml"),
+            "[styles]\n\"message.text\" = \"#839496\"",
+        )
+        .unwrap();
+        fs::write(dir.join("ping.toml"), "base = \"pong\"").unwrap();
+        fs::write(dir.join("pong.toml"), "base = \"ping\"").unwrap();
+        fs::write(dir.join("light.toml"), "[styles]\n\"popup\" = \"on white\"").unwrap();
+        fs::write(dir.join("typo.toml"), "[styles]\n\"mesage.text\" = \"red\"").unwrap();
+        let themes = Themes::new(dir.clone(), ColorDepth::Truecolor);
+
+        let solar = themes.load("solar").unwrap();
+        assert_eq!(
+            solar.style("message.text").fg,
+            Some(Color::Rgb(0x83, 0x94, 0x96))
+        );
+        // Everything else comes from the default theme
+        assert_eq!(
+            solar.style("popup"),
+            themes.load(DEFAULT_THEME).unwrap().style("popup")
+        );
+        assert!(themes.names().contains(&"solar".to_string()));
+        assert!(themes.load("typo").is_err());
+        assert!(themes.load("ping").is_err());
+        fs::write(dir.join("escape.toml"), "base = \"../solar\"").unwrap();
+        for name in ["../solar", "/etc/passwd", "..", "escape"] {
+            assert!(themes.load(name).is_err(), "{}", name);
+        }
+        // Overriding a built-in keeps the rest of it
+        let light = themes.load("light").unwrap();
+        assert_eq!(light.style("popup"), Style::new().bg(Color::White));
+        assert_eq!(light.style("message.text").fg, Some(Color::Black));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
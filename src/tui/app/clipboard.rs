@@ -0,0 +1,169 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::settings::{ClipboardMethod, ClipboardSettings};
+
+// Several terminals drop OSC 52 sequences past about this size
+const MAX_OSC52_LEN: usize = 100_000;
+// A clipboard command still running after this is killed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+const COMMAND_POLL: Duration = Duration::from_millis(20);
+
+pub struct Clipboard {
+    method: ClipboardMethod,
+    command: String,
+    file: PathBuf,
+    // Commands report back here once they are done
+    results: (
+        mpsc::Sender<io::Result<String>>,
+        mpsc::Receiver<io::Result<String>>,
+    ),
+}
+
+impl Clipboard {
+    pub fn new(settings: ClipboardSettings) -> Self {
+        Self {
+            method: settings.method,
+            command: settings.command,
+            file: settings.file,
+            results: mpsc::channel(),
+        }
+    }
+
+    // Says where the text went, so the user knows where to find it.
+    // Nothing while a command is still at it, its result comes out of finished
+    pub fn copy(&self, text: &str) -> io::Result<Option<String>> {
+        match self.method {
+            ClipboardMethod::Osc52 => {
+                let encoded = STANDARD.encode(text);
+                if encoded.len() > MAX_OSC52_LEN {
+                    return self.copy_to_file(text).map(Some);
+                }
+                // OSC 52 hands the text to the terminal, which also works over ssh
+                let mut out = io::stdout();
+                write!(out, "\x1b]52;c;{}\x07", encoded)?;
+                out.flush()?;
+                Ok(Some("the clipboard".to_string()))
+            }
+            ClipboardMethod::Command => self.copy_to_command(text).map(|_| None),
+            ClipboardMethod::File => self.copy_to_file(text).map(Some),
+        }
+    }
+
+    // Polled by the UI, one result per finished command
+    pub fn finished(&self) -> Option<io::Result<String>> {
+        self.results.1.try_recv().ok()
+    }
+
+    fn copy_to_command(&self, text: &str) -> io::Result<()> {
+        let mut parts = self.command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no clipboard command configured",
+            )
+        })?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Feeding and waiting happen off the UI thread, a stuck command would freeze it
+        let mut stdin = child.stdin.take().unwrap();
+        let (text, program) = (text.to_string(), program.to_string());
+        let sender = self.results.0.clone();
+        thread::spawn(move || {
+            let written = stdin.write_all(text.as_bytes());
+            drop(stdin);
+            let result = written
+                .and_then(|_| wait_for(&mut child, &program))
+                .map(|_| program);
+            let _ = sender.send(result);
+        });
+        Ok(())
+    }
+
+    fn copy_to_file(&self, text: &str) -> io::Result<String> {
+        if let Some(parent) = self.file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.file, text)?;
+        Ok(self.file.display().to_string())
+    }
+}
+
+// Killed and reaped past the timeout so it doesn't linger as a zombie
+fn wait_for(child: &mut Child, program: &str) -> io::Result<()> {
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= COMMAND_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not finish in time", program),
+            ));
+        }
+        thread::sleep(COMMAND_POLL);
+    };
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::networking::test::eventually;
+
+    fn clipboard(command: &str) -> Clipboard {
+        Clipboard::new(ClipboardSettings {
+            method: ClipboardMethod::Command,
+            command: command.to_string(),
+            ..Default::default()
+        })
+    }
+
+    // Copies and waits for the command to report back
+    fn copied(command: &str) -> io::Result<String> {
+        let clipboard = clipboard(command);
+        assert!(clipboard.copy("hi")?.is_none());
+        eventually(|| clipboard.finished()).unwrap()
+    }
+
+    #[test]
+    fn test_command_results() {
+        assert_eq!(copied("cat").unwrap(), "cat");
+        assert!(copied("false").is_err());
+        assert!(clipboard("").copy("hi").is_err());
+    }
+
+    #[test]
+    fn test_stuck_command_returns_at_once_then_times_out() {
+        let clipboard = clipboard("sleep 10");
+        let start = Instant::now();
+        assert!(clipboard.copy("hi").unwrap().is_none());
+        assert!(start.elapsed() < COMMAND_TIMEOUT);
+        assert!(clipboard.finished().is_none());
+
+        let error = eventually(|| clipboard.finished()).unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,66 @@
+use std::{path::PathBuf, str::FromStr};
+
+use crate::{
+    export::ExportFormat,
+    networking::{notify::NotifyLevel, presence::Presence},
+};
+
+pub const COMMAND_PREFIX: char = '/';
+
+// Usage and what it does, for the help
+pub const HELP: &[(&str, &str)] = &[
+    ("online | away | busy | invisible", "set your presence"),
+    ("status TEXT", "set your status line, empty clears it"),
+    (
+        "notify all | mentions | mute",
+        "notifications for this conversation",
+    ),
+    (
+        "export [md | html | jsonl] [PATH]",
+        "save this conversation",
+    ),
+    ("theme [NAME]", "switch themes, or list them"),
+];
+
+pub enum Command {
+    Presence(Presence),
+    Status(String),
+    Notify(NotifyLevel),
+    Export(ExportFormat, Option<PathBuf>),
+    // Without a name it lists the themes
+    Theme(Option<String>),
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.strip_prefix(COMMAND_PREFIX).unwrap_or(input);
+        let (name, args) = input.split_once(' ').unwrap_or((input, ""));
+        match name {
+            "online" => Ok(Command::Presence(Presence::Online)),
+            "away" => Ok(Command::Presence(Presence::Away)),
+            "busy" => Ok(Command::Presence(Presence::Busy)),
+            "invisible" => Ok(Command::Presence(Presence::Invisible)),
+            "status" => Ok(Command::Status(args.trim().to_string())),
+            "notify" => match args.trim() {
+                "all" => Ok(Command::Notify(NotifyLevel::All)),
+                "mentions" => Ok(Command::Notify(NotifyLevel::Mentions)),
+                "mute" | "muted" => Ok(Command::Notify(NotifyLevel::Muted)),
+                _ => Err(format!("Usage: {}notify all|mentions|mute", COMMAND_PREFIX)),
+            },
+            "export" => {
+                let mut args = args.split_whitespace();
+                let format = match args.next() {
+                    Some(format) => format.parse()?,
+                    None => ExportFormat::Markdown,
+                };
+                Ok(Command::Export(format, args.next().map(PathBuf::from)))
+            }
+            "theme" => Ok(Command::Theme(
+                Some(args.trim().to_string()).filter(|name| !name.is_empty()),
+            )),
+            _ => Err(format!("Unknown command '{}{}'", COMMAND_PREFIX, name)),
+        }
+    }
+}
@@ -1,34 +1,53 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    cmp::Reverse,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 
-use ratatui::widgets::{Block, List, ListState};
+use ratatui::{
+    text::{Line, Span},
+    widgets::{Block, List, ListState},
+};
 
-use crate::{networking::Connection, tui::config::ListConfig};
-
-use super::AppState;
+use crate::{
+    networking::{presence::Presence, Connection, Message},
+    tui::config::ListConfig,
+};
 
 pub struct ConnectionList<'a> {
     pub list: List<'a>,
     pub connections: Arc<Mutex<Vec<Connection>>>,
     pub list_state: ListState,
+    pub sort_by_activity: bool,
+    // Where the selected conversation was last read up to before we opened it
+    pub unread_marker: Option<SystemTime>,
+    viewing: Option<Connection>,
 }
 impl<'a> ConnectionList<'a> {
-    pub fn new() -> ConnectionList<'a> {
+    pub fn new(sort_by_activity: bool) -> ConnectionList<'a> {
         ConnectionList {
             list: List::new(Vec::<String>::new()),
             connections: Arc::new(Mutex::new(vec![])),
             list_state: ListState::default(),
+            sort_by_activity,
+            unread_marker: None,
+            viewing: None,
         }
     }
     pub fn update(&mut self, selected: bool) {
-        let connection_names: Vec<String> = self
+        if self.sort_by_activity {
+            self.sort();
+        }
+        self.mark_selected_read();
+        let connection_lines: Vec<Line> = self
             .connections
             .lock()
             .unwrap()
             .iter()
-            .map(|c| c.get_name())
+            .map(ConnectionList::get_line)
             .collect();
-        let conn_len = connection_names.len();
-        self.list = List::new(connection_names)
+        let conn_len = connection_lines.len();
+        self.list = List::new(connection_lines)
             .block(Block::bordered().title("Connections"))
             .style(if selected {
                 ListConfig::selected_color()
@@ -45,6 +64,116 @@ impl<'a> ConnectionList<'a> {
         }
     }
 
+    // Reorders the connections in place, the selection follows its connection
+    fn sort(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+        let mut order: Vec<usize> = (0..connections.len()).collect();
+        order.sort_by_key(|i| Reverse(connections[*i].last_activity()));
+        if order.iter().enumerate().all(|(position, i)| position == *i) {
+            return;
+        }
+        *connections = order.iter().map(|i| connections[*i].clone()).collect();
+        if let Some(selected) = self.list_state.selected() {
+            self.list_state
+                .select(order.iter().position(|i| *i == selected));
+        }
+    }
+
+    fn mark_selected_read(&mut self) {
+        let connections = self.connections.lock().unwrap();
+        let Some(connection) = self.list_state.selected().and_then(|i| connections.get(i)) else {
+            self.viewing = None;
+            return;
+        };
+        if !self.viewing.as_ref().is_some_and(|c| c.same_as(connection)) {
+            self.unread_marker = Some(connection.last_read());
+            self.viewing = Some(connection.clone());
+        }
+        connection.mark_read();
+    }
+
+    pub fn with_selected_messages<R>(&self, f: impl FnOnce(&[Message]) -> R) -> Option<R> {
+        let index = self.list_state.selected()?;
+        let connections = self.connections.lock().unwrap();
+        let messages = connections.get(index)?.messages.lock().unwrap();
+        Some(f(&messages))
+    }
+
+    pub fn selected(&self) -> Option<Connection> {
+        let index = self.list_state.selected()?;
+        self.connections.lock().unwrap().get(index).cloned()
+    }
+
+    // Brings back a conversation as it was left, unread divider included
+    pub fn select_connection(
+        &mut self,
+        connection: &Connection,
+        unread_marker: Option<SystemTime>,
+    ) {
+        let index = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|c| c.same_as(connection));
+        if index.is_some() {
+            self.list_state.select(index);
+            self.viewing = Some(connection.clone());
+            self.unread_marker = unread_marker;
+        }
+    }
+
+    pub fn select_next_unread(&mut self) {
+        let connections = self.connections.lock().unwrap();
+        let start = self.list_state.selected().map_or(0, |i| i + 1);
+        let next = (0..connections.len())
+            .map(|offset| (start + offset) % connections.len())
+            .find(|i| connections[*i].unread_count() > 0);
+        if next.is_some() {
+            self.list_state.select(next);
+        }
+    }
+
+    pub fn get_line(connection: &Connection) -> Line<'a> {
+        let status = connection.peer_status();
+        let presence = if connection.is_alive() {
+            status.presence
+        } else {
+            Presence::Invisible
+        };
+        let mut spans = vec![Span::styled(
+            ListConfig::presence_symbol(presence),
+            ListConfig::presence_style(presence),
+        )];
+        let unread = connection.unread_count();
+        if unread > 0 {
+            spans.push(Span::styled(
+                connection.get_name(),
+                ListConfig::unread_style(),
+            ));
+            spans.push(Span::styled(
+                format!(" ({})", unread),
+                ListConfig::unread_badge_style(),
+            ));
+            let mentions = connection.unread_mentions();
+            if mentions > 0 {
+                spans.push(Span::styled(
+                    format!(" @{}", mentions),
+                    ListConfig::mention_badge_style(),
+                ));
+            }
+        } else {
+            spans.push(Span::raw(connection.get_name()));
+        }
+        if !status.text.is_empty() {
+            spans.push(Span::styled(
+                format!(" - {}", status.text),
+                ListConfig::status_style(),
+            ));
+        }
+        Line::from(spans)
+    }
+
     pub fn iterate_selected(&mut self, step: i32) {
         if self.list_state.selected().is_none() {
             if step > 0 {
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use ratatui::style::Style;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxSet};
+
+use crate::tui::config::MessageConfig;
+
+// Enough for every block on screen and then some, the cache starts over past this
+const MAX_CACHED_BLOCKS: usize = 256;
+
+// Grammars are bundled into the binary, loading them takes a moment so it only happens once
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+
+// Parsing is too slow to redo every frame. Keyed by language and code, so an edited message
+// gets parsed again. Kinds rather than styles are kept so switching themes needs no reparse
+static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+
+type Highlighted = Vec<Vec<(String, Kind)>>;
+type Cache = HashMap<(String, String), Arc<Highlighted>>;
+
+fn syntaxes() -> &'static SyntaxSet {
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    Plain,
+    Comment,
+    String,
+    Constant,
+    Keyword,
+    Function,
+    Type,
+}
+
+impl Kind {
+    fn style(self) -> Style {
+        match self {
+            Kind::Plain => MessageConfig::code_block_style(),
+            Kind::Comment => MessageConfig::code_comment_style(),
+            Kind::String => MessageConfig::code_string_style(),
+            Kind::Constant => MessageConfig::code_constant_style(),
+            Kind::Keyword => MessageConfig::code_keyword_style(),
+            Kind::Function => MessageConfig::code_function_style(),
+            Kind::Type => MessageConfig::code_type_style(),
+        }
+    }
+}
+
+// Styled segments for each line of a fenced block, the language being the tag after the fence
+pub fn block(language: &str, code: &[&str]) -> Vec<Vec<(String, Style)>> {
+    let Some(highlighted) = cached(language, code) else {
+        return code
+            .iter()
+            .map(|line| vec![(line.to_string(), Kind::Plain.style())])
+            .collect();
+    };
+    highlighted
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|(text, kind)| (text.clone(), kind.style()))
+                .collect()
+        })
+        .collect()
+}
+
+fn cached(language: &str, code: &[&str]) -> Option<Arc<Highlighted>> {
+    let key = (language.trim().to_string(), code.join("\n"));
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(highlighted) = cache.lock().unwrap().get(&key) {
+        return Some(Arc::clone(highlighted));
+    }
+    let mut highlighter = CodeHighlighter::new(language)?;
+    let highlighted = Arc::new(code.iter().map(|line| highlighter.line(line)).collect());
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= MAX_CACHED_BLOCKS {
+        cache.clear();
+    }
+    cache.insert(key, Arc::clone(&highlighted));
+    Some(highlighted)
+}
+
+// Highlights one fenced block line by line, the parser state carries over between lines
+pub struct CodeHighlighter {
+    state: ParseState,
+    stack: ScopeStack,
+}
+
+impl CodeHighlighter {
+    // The tag after the fence, like "rust", "sh" or "py"
+    pub fn new(language: &str) -> Option<Self> {
+        let syntax = syntaxes().find_syntax_by_token(language.trim())?;
+        Some(Self {
+            state: ParseState::new(syntax),
+            stack: ScopeStack::new(),
+        })
+    }
+
+    pub fn line(&mut self, line: &str) -> Vec<(String, Kind)> {
+        let Ok(ops) = self.state.parse_line(&format!("{}\n", line), syntaxes()) else {
+            return vec![(line.to_string(), Kind::Plain)];
+        };
+        let mut segments = vec![];
+        let mut last = 0;
+        for (offset, op) in ops {
+            let offset = offset.min(line.len());
+            if offset > last {
+                CodeHighlighter::push(&mut segments, &line[last..offset], self.kind());
+                last = offset;
+            }
+            if self.stack.apply(&op).is_err() {
+                break;
+            }
+        }
+        if last < line.len() {
+            CodeHighlighter::push(&mut segments, &line[last..], self.kind());
+        }
+        segments
+    }
+
+    // Neighbouring text of the same kind shares a span
+    fn push(segments: &mut Vec<(String, Kind)>, text: &str, kind: Kind) {
+        match segments.last_mut() {
+            Some((last, last_kind)) if *last_kind == kind => last.push_str(text),
+            _ => segments.push((text.to_string(), kind)),
+        }
+    }
+
+    // The innermost scope we have a colour for wins
+    fn kind(&self) -> Kind {
+        self.stack
+            .as_slice()
+            .iter()
+            .rev()
+            .find_map(CodeHighlighter::scope_kind)
+            .unwrap_or(Kind::Plain)
+    }
+
+    fn scope_kind(scope: &Scope) -> Option<Kind> {
+        let name = scope.build_string();
+        let is = |prefix: &str| name.starts_with(prefix);
+        if is("comment") {
+            Some(Kind::Comment)
+        } else if is("string") {
+            Some(Kind::String)
+        } else if is("constant") {
+            Some(Kind::Constant)
+        } else if is("keyword") || is("storage") {
+            Some(Kind::Keyword)
+        } else if is("entity.name.function") || is("support.function") {
+            Some(Kind::Function)
+        } else if is("entity.name") || is("support.type") {
+            Some(Kind::Type)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kind_of(segments: &[(String, Kind)], text: &str) -> Option<Kind> {
+        segments
+            .iter()
+            .find(|(segment, _)| segment.trim() == text)
+            .map(|(_, kind)| *kind)
+    }
+
+    #[test]
+    fn test_highlighter_classifies_tokens() {
+        assert!(CodeHighlighter::new("no-such-language").is_none());
+        let mut highlighter = CodeHighlighter::new("rust").unwrap();
+        let first = highlighter.line("fn main() {");
+        assert_eq!(kind_of(&first, "fn"), Some(Kind::Keyword));
+        assert_eq!(kind_of(&first, "main"), Some(Kind::Function));
+        // A comment opened on one line still colours the next
+        highlighter.line("    /* spans");
+        let second = highlighter.line("       lines */");
+        assert_eq!(second.last().map(|(_, kind)| *kind), Some(Kind::Comment));
+        let text: String = first.into_iter().map(|(text, _)| text).collect();
+        assert_eq!(text, "fn main() {");
+    }
+
+    #[test]
+    fn test_blocks_are_cached() {
+        let code = ["let answer = \"42\";"];
+        let first = cached("rust", &code).unwrap();
+        let second = cached(" rust ", &code).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(cached("rust", &["let answer = 43;"]).is_some_and(|c| !Arc::ptr_eq(&first, &c)));
+        assert!(cached("no-such-language", &code).is_none());
+        assert_eq!(block("no-such-language", &code).len(), 1);
+    }
+}
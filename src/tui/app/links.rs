@@ -0,0 +1,221 @@
+use std::{
+    io,
+    ops::Range,
+    process::{Command, Stdio},
+    sync::OnceLock,
+    thread,
+};
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::Line,
+    widgets::{Block, List, ListState},
+};
+use regex::Regex;
+
+use super::picker;
+use crate::{
+    networking::Message,
+    settings::LinkSettings,
+    tui::config::{ListConfig, MessageConfig},
+};
+
+// Control and format characters never make it into a link, so a peer can't smuggle
+// escape sequences or bidi overrides into the terminal or the open command
+static URL: OnceLock<Regex> = OnceLock::new();
+
+fn url_regex() -> &'static Regex {
+    URL.get_or_init(|| Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s<>"'`\p{Cc}\p{Cf}]+"#).unwrap())
+}
+
+// Byte ranges of the links in `text`, without trailing punctuation like "see https://x.org."
+pub fn find(text: &str) -> Vec<Range<usize>> {
+    url_regex()
+        .find_iter(text)
+        .map(|found| {
+            let url = found
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}']);
+            found.start()..found.start() + url.len()
+        })
+        .collect()
+}
+
+// Wraps every visible occurrence of a known link in an OSC 8 hyperlink. Links are matched
+// per row, so only ones that fit on a single row are clickable
+pub fn hyperlink(buffer: &mut Buffer, area: Rect, urls: &[String]) {
+    // Longer links first, so one that contains another isn't wrapped twice
+    let mut urls: Vec<&String> = urls.iter().collect();
+    urls.sort_by_key(|url| std::cmp::Reverse(url.len()));
+    for y in area.top()..area.bottom() {
+        let mut row = String::new();
+        let mut cells = vec![];
+        for x in area.left()..area.right() {
+            let symbol = buffer[(x, y)].symbol();
+            cells.extend(std::iter::repeat_n(x, symbol.len()));
+            row.push_str(symbol);
+        }
+        let mut linked: Vec<Range<u16>> = vec![];
+        for url in &urls {
+            for (start, _) in row.match_indices(url.as_str()) {
+                let columns = cells[start]..cells[start + url.len() - 1] + 1;
+                if linked
+                    .iter()
+                    .any(|done| done.start < columns.end && columns.start < done.end)
+                {
+                    continue;
+                }
+                let symbols: Vec<String> = columns
+                    .clone()
+                    .map(|x| buffer[(x, y)].symbol().to_string())
+                    .collect();
+                // Terminals miscount the width of escape sequences, two cells per chunk
+                // keeps the rest of the row where it belongs
+                for (i, chunk) in symbols.chunks(2).enumerate() {
+                    let link = format!("\x1b]8;;{}\x07{}\x1b]8;;\x07", url, chunk.concat());
+                    buffer[(columns.start + i as u16 * 2, y)].set_symbol(&link);
+                }
+                linked.push(columns);
+            }
+        }
+    }
+}
+
+// Every link in a conversation, newest first
+pub fn collect(messages: &[Message]) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    for message in messages.iter().rev() {
+        for range in find(&message.content) {
+            let url = &message.content[range];
+            if !links.iter().any(|link| link == url) {
+                links.push(url.to_string());
+            }
+        }
+    }
+    links
+}
+
+pub struct LinkPicker {
+    pub links: Vec<String>,
+    pub list_state: ListState,
+    open_command: String,
+}
+
+impl LinkPicker {
+    pub fn new(settings: &LinkSettings) -> Self {
+        Self {
+            links: vec![],
+            list_state: ListState::default(),
+            open_command: settings.open_command.clone(),
+        }
+    }
+
+    pub fn update(&mut self, messages: &[Message]) {
+        self.links = collect(messages);
+        if self.links.is_empty() {
+            self.list_state.select(None);
+        } else {
+            self.list_state.select_first();
+        }
+    }
+
+    pub fn iterate_selected(&mut self, step: i32) {
+        picker::step(&mut self.list_state, self.links.len(), step);
+    }
+
+    pub fn widget(&self) -> List<'static> {
+        let lines: Vec<Line> = self
+            .links
+            .iter()
+            .map(|link| Line::styled(link.clone(), MessageConfig::link_style()))
+            .collect();
+        List::new(lines)
+            .block(Block::bordered().title(format!("Links ({})", self.links.len())))
+            .style(ListConfig::selected_color())
+            .highlight_style(ListConfig::highlight())
+            .highlight_symbol(">>")
+    }
+
+    // The url is passed as its own argument, never through a shell
+    pub fn open_selected(&self) -> io::Result<()> {
+        let Some(url) = self.list_state.selected().and_then(|i| self.links.get(i)) else {
+            return Ok(());
+        };
+        let mut parts = self.open_command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no open command configured")
+        })?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Reap it in the background so it doesn't linger as a zombie
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find(text).into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn test_trailing_punctuation_is_left_out() {
+        assert_eq!(urls("see https://x.org."), ["https://x.org"]);
+        assert_eq!(
+            urls("(https://x.org/a?b=1), or FTP://x.org/f!"),
+            ["https://x.org/a?b=1", "FTP://x.org/f"]
+        );
+        assert_eq!(urls("<https://x.org/\"quoted\">"), ["https://x.org/"]);
+        assert!(urls("no links, just x.org and mailto:a@x.org").is_empty());
+    }
+
+    #[test]
+    fn test_control_and_format_characters_end_a_link() {
+        for sneaky in [
+            "\x1b]8;;https://evil.example\x07",
+            "\x07",
+            "\u{9b}31m",
+            "\u{202e}gpj.exe",
+            "\u{2066}x",
+            "\u{200b}",
+        ] {
+            let text = format!("https://x.org/a{}", sneaky);
+            let found = urls(&text);
+            assert_eq!(found[0], "https://x.org/a", "{:?}", sneaky);
+            assert!(found
+                .iter()
+                .all(|url| !url.contains(|c: char| c.is_control() || c > '\u{7f}')));
+        }
+    }
+
+    #[test]
+    fn test_hyperlinks_wrap_visible_links() {
+        let area = Rect::new(0, 0, 24, 2);
+        let mut buffer = Buffer::empty(area);
+        buffer.set_string(0, 0, "go https://a.b now", ratatui::style::Style::new());
+        buffer.set_string(0, 1, "https://a.b/c", ratatui::style::Style::new());
+        hyperlink(
+            &mut buffer,
+            area,
+            &["https://a.b".to_string(), "https://a.b/c".to_string()],
+        );
+
+        let link = |url: &str, text: &str| format!("\x1b]8;;{}\x07{}\x1b]8;;\x07", url, text);
+        assert_eq!(buffer[(2, 0)].symbol(), " ");
+        assert_eq!(buffer[(3, 0)].symbol(), link("https://a.b", "ht"));
+        assert_eq!(buffer[(13, 0)].symbol(), link("https://a.b", "b"));
+        assert_eq!(buffer[(14, 0)].symbol(), " ");
+        // The longer link wins where they overlap
+        assert_eq!(buffer[(0, 1)].symbol(), link("https://a.b/c", "ht"));
+        assert_eq!(buffer[(12, 1)].symbol(), link("https://a.b/c", "c"));
+    }
+}
@@ -0,0 +1,330 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+};
+use regex::Regex;
+
+use super::{highlight, links, message_box::MessageBox};
+use crate::tui::config::MessageConfig;
+
+pub const FENCE: &str = "```";
+
+// Turns message content into styled lines: inline *bold*, _italic_ and `code`,
+// fenced code blocks, > quotes and - lists
+pub fn render(content: &str, style: Style, search: Option<&Regex>) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    let mut source = content.lines();
+    while let Some(line) = source.next() {
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix(FENCE) {
+            // ```a one line block```
+            if let Some(code) = rest.strip_suffix(FENCE) {
+                let segments = vec![(code.to_string(), MessageConfig::code_block_style())];
+                lines.push(code_line(segments, search));
+                continue;
+            }
+            // Up to the closing fence, or the end for an unterminated block
+            let code: Vec<&str> = source
+                .by_ref()
+                .take_while(|line| !line.trim_start().starts_with(FENCE))
+                .collect();
+            for segments in highlight::block(rest, &code) {
+                lines.push(code_line(segments, search));
+            }
+            continue;
+        }
+        if let Some(quote) = trimmed.strip_prefix('>') {
+            let quote_style = style.patch(MessageConfig::quote_style());
+            let mut spans = vec![Span::styled("▎ ", quote_style)];
+            spans.extend(inline(quote.trim_start(), quote_style, search));
+            lines.push(Line::from(spans));
+        } else if let Some((bullet, item)) = list_item(trimmed) {
+            let mut spans = vec![Span::styled(
+                format!("  {} ", bullet),
+                MessageConfig::list_bullet_style(),
+            )];
+            spans.extend(inline(item, style, search));
+            lines.push(Line::from(spans));
+        } else {
+            lines.push(Line::from(inline(line, style, search)));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(Line::default());
+    }
+    lines
+}
+
+// Content exactly as it was sent, one line per line
+pub fn render_raw(content: &str, style: Style, search: Option<&Regex>) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line> = content
+        .lines()
+        .map(|line| Line::from(MessageBox::highlight(line, style, search)))
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::default());
+    }
+    lines
+}
+
+// A block of text sent as one fenced message, shown collapsed when long
+pub fn snippet(text: &str) -> String {
+    format!("{}\n{}\n{}", FENCE, text.trim_end_matches('\n'), FENCE)
+}
+
+// Contents of every fenced block, in order
+pub fn code_blocks(content: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in content.lines() {
+        let Some(rest) = line.trim_start().strip_prefix(FENCE) else {
+            if let Some(block) = current.as_mut() {
+                block.push(line);
+            }
+            continue;
+        };
+        match current.take() {
+            Some(block) => blocks.push(block.join("\n")),
+            None => match rest.strip_suffix(FENCE) {
+                Some(code) => blocks.push(code.to_string()),
+                None => current = Some(vec![]),
+            },
+        }
+    }
+    // An unterminated fence still counts as a block
+    if let Some(block) = current {
+        blocks.push(block.join("\n"));
+    }
+    blocks
+}
+
+fn code_line(segments: Styled, search: Option<&Regex>) -> Line<'static> {
+    let style = MessageConfig::code_block_style();
+    let mut spans = vec![Span::styled("  ", style)];
+    for (content, style) in segments {
+        spans.extend(MessageBox::highlight(&content, style, search));
+    }
+    spans.push(Span::styled("  ", style));
+    Line::from(spans)
+}
+
+// Unordered items get a bullet, numbered ones keep their number
+fn list_item(line: &str) -> Option<(String, &str)> {
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(marker) {
+            return Some(("•".to_string(), item));
+        }
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let item = line[digits..].strip_prefix(". ")?;
+    (digits > 0).then(|| (line[..digits + 1].to_string(), item))
+}
+
+fn inline(text: &str, style: Style, search: Option<&Regex>) -> Vec<Span<'static>> {
+    let mut spans = vec![];
+    let mut plain_start = 0;
+    let mut position = 0;
+    // Links are taken as they are, underscores in them aren't emphasis
+    let mut urls = links::find(text).into_iter().peekable();
+    let bold_closers = closers(text, '*');
+    let italic_closers = closers(text, '_');
+    while let Some(c) = text[position..].chars().next() {
+        let found = match c {
+            _ if urls.peek().is_some_and(|url| url.start == position) => {
+                let url = urls.next().unwrap();
+                Some((
+                    url.end,
+                    vec![(
+                        text[url].to_string(),
+                        style.patch(MessageConfig::link_style()),
+                    )],
+                ))
+            }
+            '`' => code_span(text, position),
+            '*' => emphasis(
+                text,
+                position,
+                &bold_closers,
+                style.add_modifier(Modifier::BOLD),
+            ),
+            '_' => emphasis(
+                text,
+                position,
+                &italic_closers,
+                style.add_modifier(Modifier::ITALIC),
+            ),
+            _ => None,
+        };
+        let Some((end, styled)) = found else {
+            position += c.len_utf8();
+            continue;
+        };
+        if plain_start < position {
+            spans.extend(MessageBox::highlight(
+                &text[plain_start..position],
+                style,
+                search,
+            ));
+        }
+        for (content, style) in styled {
+            spans.extend(MessageBox::highlight(&content, style, search));
+        }
+        position = end;
+        plain_start = end;
+        while urls.peek().is_some_and(|url| url.start < position) {
+            urls.next();
+        }
+    }
+    if plain_start < text.len() || spans.is_empty() {
+        spans.extend(MessageBox::highlight(&text[plain_start..], style, search));
+    }
+    spans
+}
+
+type Styled = Vec<(String, Style)>;
+
+// Everything between two backticks is literal
+fn code_span(text: &str, start: usize) -> Option<(usize, Styled)> {
+    let length = text[start + 1..].find('`')?;
+    if length == 0 {
+        return None;
+    }
+    let code = &text[start + 1..start + 1 + length];
+    Some((
+        start + length + 2,
+        vec![(code.to_string(), MessageConfig::code_style())],
+    ))
+}
+
+// Every position a delimiter could close emphasis, found in one pass so openers without
+// a partner don't each rescan the rest of the line
+fn closers(text: &str, delimiter: char) -> Vec<usize> {
+    text.match_indices(delimiter)
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            !text[..i].ends_with(char::is_whitespace)
+                && !text[i + 1..].starts_with(char::is_alphanumeric)
+        })
+        .collect()
+}
+
+// Delimiters only count at word boundaries, so snake_case and 2*3*4 stay as they are
+fn emphasis(text: &str, start: usize, closers: &[usize], style: Style) -> Option<(usize, Styled)> {
+    if text[..start].ends_with(char::is_alphanumeric) {
+        return None;
+    }
+    // Both delimiters are a single byte
+    let inner_start = start + 1;
+    if text[inner_start..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = *closers.get(closers.partition_point(|&i| i <= inner_start))?;
+    // Nested emphasis like *_both_* keeps both modifiers
+    let styled = inline_segments(&text[inner_start..end], style);
+    Some((end + 1, styled))
+}
+
+fn inline_segments(text: &str, style: Style) -> Styled {
+    inline(text, style, None)
+        .into_iter()
+        .map(|span| (span.content.to_string(), span.style))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Each span's text with b for bold, i for italic
+    fn spans(text: &str) -> Vec<(String, &'static str)> {
+        render(text, Style::new(), None)
+            .into_iter()
+            .flat_map(|line| line.spans)
+            .map(|span| {
+                let bold = span.style.add_modifier.contains(Modifier::BOLD);
+                let italic = span.style.add_modifier.contains(Modifier::ITALIC);
+                let marks = match (bold, italic) {
+                    (true, true) => "bi",
+                    (true, false) => "b",
+                    (false, true) => "i",
+                    (false, false) => "",
+                };
+                (span.content.to_string(), marks)
+            })
+            .collect()
+    }
+
+    fn text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    fn owned(expected: &[(&str, &'static str)]) -> Vec<(String, &'static str)> {
+        expected
+            .iter()
+            .map(|(content, marks)| (content.to_string(), *marks))
+            .collect()
+    }
+
+    #[test]
+    fn test_emphasis() {
+        assert_eq!(
+            spans("a *bold* and _italic_"),
+            owned(&[("a ", ""), ("bold", "b"), (" and ", ""), ("italic", "i")])
+        );
+        assert_eq!(spans("*_both_*"), owned(&[("both", "bi")]));
+        assert_eq!(
+            spans("*bold _and_ more*"),
+            owned(&[("bold ", "b"), ("and", "bi"), (" more", "b")])
+        );
+    }
+
+    #[test]
+    fn test_delimiters_inside_words_are_literal() {
+        assert_eq!(spans("snake_case_name"), owned(&[("snake_case_name", "")]));
+        assert_eq!(spans("2*3*4"), owned(&[("2*3*4", "")]));
+        assert_eq!(spans("a * not bold *"), owned(&[("a * not bold *", "")]));
+        assert_eq!(spans("*unclosed"), owned(&[("*unclosed", "")]));
+    }
+
+    #[test]
+    fn test_code_spans_are_literal() {
+        let lines = render("run `*not bold*` now", Style::new(), None);
+        assert_eq!(text(&lines[0]), "run *not bold* now");
+        assert!(lines[0]
+            .spans
+            .iter()
+            .all(|span| !span.style.add_modifier.contains(Modifier::BOLD)));
+        assert_eq!(spans("``"), owned(&[("``", "")]));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let lines = render(
+            "> *quoted*\n- item\n2. second\n```\n*code*\n```",
+            Style::new(),
+            None,
+        );
+        let texts: Vec<String> = lines.iter().map(text).collect();
+        assert_eq!(texts, ["▎ quoted", "  • item", "  2. second", "  *code*  "]);
+        assert!(lines[0].spans[1]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+        assert_eq!(
+            code_blocks("```rust\nfn main() {}\n```\ntext\n```one```\n```\nopen"),
+            ["fn main() {}", "one", "open"]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_delimiters_stay_fast() {
+        // Quadratic rescanning took seconds on this
+        let content = "*a _b ".repeat(20_000);
+        let lines = render(&content, Style::new(), None);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(text(&lines[0]), content);
+    }
+}
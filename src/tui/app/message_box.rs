@@ -1,45 +1,416 @@
-use std::time::SystemTime;
+use std::{collections::HashSet, ops::RangeInclusive, time::SystemTime};
 
-use chrono::{DateTime, Local};
 use ratatui::{
+    layout::Rect,
+    style::Style,
     text::{Line, Span},
     widgets::{Block, Paragraph, Wrap},
 };
+use regex::Regex;
+
+use super::markdown;
+use crate::{
+    networking::{payload::MessageId, reactions, Message, MessageType},
+    settings::TimeSettings,
+    tui::config::MessageConfig,
+};
+
+const QUOTE_LEN: usize = 60;
+// Snippets longer than this only show their start until expanded
+const COLLAPSED_LINES: usize = 8;
+const DELETED_TEXT: &str = "message deleted";
+
+#[derive(Default)]
+pub struct MessageView {
+    // Message to keep in view, None sticks to the newest message. Held by id, positions
+    // shift whenever old messages are dropped
+    pub focus: Option<MessageId>,
+    pub search: Option<Regex>,
+    // Shows content without markdown formatting
+    pub raw: bool,
+    // Other end of a range selection, the focus being the end that moves
+    pub anchor: Option<MessageId>,
+    // Long snippets that were opened up
+    pub expanded: HashSet<MessageId>,
+    // Rows scrolled back from the bottom while nothing is focused
+    pub scroll: usize,
+}
+
+impl MessageView {
+    pub fn focus_index(&self, messages: &[Message]) -> Option<usize> {
+        position(messages, self.focus?)
+    }
+
+    pub fn focus_on(&mut self, messages: &[Message], index: Option<usize>) {
+        self.focus = index.and_then(|i| messages.get(i)).map(|m| m.id);
+    }
+
+    // A range whose anchor was dropped shrinks to the focus
+    pub fn selection(&self, messages: &[Message]) -> Option<RangeInclusive<usize>> {
+        let focus = self.focus_index(messages)?;
+        let anchor = self
+            .anchor
+            .and_then(|id| position(messages, id))
+            .unwrap_or(focus);
+        Some(focus.min(anchor)..=focus.max(anchor))
+    }
+
+    // Back to following the newest message
+    pub fn follow_newest(&mut self) {
+        self.focus = None;
+        self.scroll = 0;
+    }
+}
+
+fn position(messages: &[Message], id: MessageId) -> Option<usize> {
+    messages.iter().position(|m| m.id == id)
+}
 
-use crate::{networking::Message, tui::config::MessageConfig};
 pub struct MessageBox {}
 
 impl MessageBox {
-    pub fn new(messages: &Vec<Message>) -> Paragraph {
-        Paragraph::new(
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        messages: &[Message],
+        unread_marker: Option<SystemTime>,
+        view: &mut MessageView,
+        area: Rect,
+        title: String,
+        focused: bool,
+        time: &TimeSettings,
+    ) -> Paragraph<'static> {
+        let first_unread = unread_marker.and_then(|marker| {
             messages
-                .into_iter()
-                .map(|m| MessageBox::get_line(m))
-                .collect::<Vec<Line>>()
-                .clone(),
-        )
-        .wrap(Wrap { trim: true })
-        .block(Block::bordered().title("Messages"))
-    }
-    fn get_line(message: &Message) -> Line {
-        Line::from(vec![
+                .iter()
+                .position(|m| m.time > marker && !m.sent_by_self)
+        });
+        let focus = view.focus_index(messages);
+        let selection = view.selection(messages);
+        let now = SystemTime::now();
+        let mut lines: Vec<Line> = vec![];
+        let mut focus_line = None;
+        let mut previous: Option<&Message> = None;
+        for (i, message) in messages.iter().enumerate() {
+            let date = time.date(message.time);
+            if previous.is_none_or(|p| time.date(p.time) != date) {
+                lines.push(
+                    Line::styled(
+                        format!("── {} ──", time.date_label(date, now)),
+                        MessageConfig::date_separator_style(),
+                    )
+                    .centered(),
+                );
+            }
+            if first_unread == Some(i) {
+                lines.push(
+                    Line::styled("── new messages ──", MessageConfig::unread_marker_style())
+                        .centered(),
+                );
+            }
+            // Text straight after the same sender's, with nothing drawn in between
+            let grouped = first_unread != Some(i)
+                && message.reply_to.is_none()
+                && previous.is_some_and(|p| {
+                    p.message_type == MessageType::Text
+                        && message.message_type == MessageType::Text
+                        && p.sent_by_self == message.sent_by_self
+                        && p.system == message.system
+                        && p.sender_name == message.sender_name
+                        && time.same_group(p.time, message.time)
+                });
+            previous = Some(message);
+            if focus == Some(i) {
+                focus_line = Some(lines.len());
+            }
+            if let Some(parent) = message.reply_to {
+                lines.push(MessageBox::quote_line(messages, parent));
+            }
+            let selected = selection.as_ref().is_some_and(|range| range.contains(&i));
+            lines.extend(MessageBox::get_lines(
+                message,
+                view,
+                selected,
+                grouped,
+                |t| time.time(t, now),
+            ));
+            if !message.reactions.is_empty() {
+                lines.push(MessageBox::reaction_line(message));
+            }
+        }
+        let scroll = MessageBox::scroll_offset(&lines, focus_line, view, area);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(title).border_style(if focused {
+                MessageConfig::focused_pane_style()
+            } else {
+                Style::new()
+            }))
+            .scroll((scroll, 0))
+    }
+
+    // Centers the focused line, or shows the bottom when nothing is focused. Scrolling back
+    // past the first line is clamped here, where the height is known
+    fn scroll_offset(
+        lines: &[Line],
+        focus: Option<usize>,
+        view: &mut MessageView,
+        area: Rect,
+    ) -> u16 {
+        let width = area.width.saturating_sub(2);
+        let height = area.height.saturating_sub(2) as usize;
+        let heights: Vec<usize> = lines
+            .iter()
+            .map(|line| MessageBox::line_height(line, width))
+            .collect();
+        let bottom = heights.iter().sum::<usize>().saturating_sub(height);
+        let offset = match focus {
+            Some(index) => heights[..index.min(heights.len())]
+                .iter()
+                .sum::<usize>()
+                .saturating_sub(height / 2)
+                .min(bottom),
+            None => {
+                view.scroll = view.scroll.min(bottom);
+                bottom - view.scroll
+            }
+        };
+        offset.min(u16::MAX as usize) as u16
+    }
+
+    // Rows a line takes once wrapped. Most fit on one and skip the wrapping, the rest are
+    // measured over borrowed spans rather than a copy of the text
+    fn line_height(line: &Line, width: u16) -> usize {
+        if line.width() <= width as usize {
+            return 1;
+        }
+        let spans: Vec<Span> = line
+            .spans
+            .iter()
+            .map(|span| Span::styled(span.content.as_ref(), span.style))
+            .collect();
+        Paragraph::new(Line::from(spans))
+            .wrap(Wrap { trim: false })
+            .line_count(width)
+    }
+
+    // The header goes in front of the first content line, the rest follow as is. Under a
+    // shared header the name is left blank so the text still lines up
+    fn get_lines(
+        message: &Message,
+        view: &MessageView,
+        focused: bool,
+        grouped: bool,
+        time_format: impl Fn(SystemTime) -> String,
+    ) -> Vec<Line<'static>> {
+        let content_style = if message.message_type == MessageType::Error {
+            MessageConfig::error_style()
+        } else if message.is_mention {
+            MessageConfig::mention_style()
+        } else {
+            MessageConfig::text_style()
+        };
+        let mut spans = vec![
             Span::styled(
-                format!("[{}] ", MessageBox::time_format(message.time)),
-                MessageConfig::time_style(),
+                format!("[{}] ", time_format(message.time)),
+                if focused {
+                    MessageConfig::focused_time_style()
+                } else {
+                    MessageConfig::time_style()
+                },
             ),
             Span::styled(
                 message.sender_name.clone(),
                 MessageConfig::username_style(message.sent_by_self),
             ),
-            Span::styled(
-                format!(" :  {}", message.content.clone()),
-                MessageConfig::text_style(),
+            Span::styled(" :  ", content_style),
+        ];
+        if grouped {
+            let width = spans[1].width() + spans[2].width();
+            spans.truncate(1);
+            spans.push(Span::raw(" ".repeat(width)));
+        }
+        let search = view.search.as_ref();
+        if message.deleted {
+            spans.push(Span::styled(DELETED_TEXT, MessageConfig::tombstone_style()));
+            return vec![Line::from(spans)];
+        }
+        let mut lines = if view.raw {
+            markdown::render_raw(&message.content, content_style, search)
+        } else {
+            markdown::render(&message.content, content_style, search)
+        };
+        let is_snippet = message.content.trim_start().starts_with(markdown::FENCE);
+        if is_snippet && lines.len() > COLLAPSED_LINES && !view.expanded.contains(&message.id) {
+            let hidden = lines.len() - COLLAPSED_LINES;
+            lines.truncate(COLLAPSED_LINES);
+            lines.push(Line::styled(
+                format!("  ⋯ {} more lines, z in selection mode expands", hidden),
+                MessageConfig::time_style(),
+            ));
+        }
+        // A leading code block starts on its own line
+        if !view.raw && is_snippet {
+            lines.insert(0, Line::from(spans));
+        } else {
+            spans.extend(lines.remove(0).spans);
+            lines.insert(0, Line::from(spans));
+        }
+        if let Some(edited) = message.edited {
+            lines.last_mut().unwrap().push_span(Span::styled(
+                format!(" (edited {})", time_format(edited)),
+                MessageConfig::time_style(),
+            ));
+        }
+        lines
+    }
+
+    // Each emoji once with its count, ours stand out
+    fn reaction_line(message: &Message) -> Line<'static> {
+        let mut spans = vec![Span::raw("   ")];
+        for (emoji, count, by_self) in reactions::aggregate(&message.reactions) {
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                format!(" {} {} ", emoji, count),
+                MessageConfig::reaction_style(by_self),
+            ));
+        }
+        Line::from(spans)
+    }
+
+    // One line summary of the message being replied to
+    fn quote_line(messages: &[Message], parent: MessageId) -> Line<'static> {
+        let quote = match messages.iter().find(|m| m.id == parent) {
+            Some(parent) if parent.deleted => {
+                format!("  ↱ {}: {}", parent.sender_name, DELETED_TEXT)
+            }
+            Some(parent) => format!(
+                "  ↱ {}: {}",
+                parent.sender_name,
+                MessageBox::preview(&parent.content)
             ),
-        ])
+            None => "  ↱ message no longer in history".to_string(),
+        };
+        Line::styled(quote, MessageConfig::quote_style())
+    }
+
+    // First line of a message, cut short enough to sit above another one
+    pub fn preview(content: &str) -> String {
+        let first = content.lines().next().unwrap_or_default();
+        let mut preview: String = first.chars().take(QUOTE_LEN).collect();
+        if first.chars().count() > QUOTE_LEN || content.lines().nth(1).is_some() {
+            preview.push('…');
+        }
+        preview
+    }
+
+    pub fn highlight(content: &str, style: Style, search: Option<&Regex>) -> Vec<Span<'static>> {
+        let Some(search) = search else {
+            return vec![Span::styled(content.to_string(), style)];
+        };
+        let mut spans = vec![];
+        let mut last = 0;
+        for found in search.find_iter(content) {
+            if found.start() > last {
+                spans.push(Span::styled(
+                    content[last..found.start()].to_string(),
+                    style,
+                ));
+            }
+            spans.push(Span::styled(
+                found.as_str().to_string(),
+                MessageConfig::search_match_style(),
+            ));
+            last = found.end();
+        }
+        if last < content.len() || spans.is_empty() {
+            spans.push(Span::styled(content[last..].to_string(), style));
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(count: usize) -> Vec<Message> {
+        (0..count)
+            .map(|i| Message {
+                id: i as MessageId + 100,
+                reply_to: None,
+                time: SystemTime::now(),
+                sent_by_self: false,
+                system: false,
+                sender_name: "bob".to_string(),
+                message_type: MessageType::Text,
+                content: i.to_string(),
+                is_mention: false,
+                edited: None,
+                deleted: false,
+                reactions: vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_selection_runs_between_anchor_and_focus() {
+        let mut messages = messages(10);
+        let mut view = MessageView::default();
+        assert_eq!(view.selection(&messages), None);
+        view.focus_on(&messages, Some(4));
+        assert_eq!(view.selection(&messages), Some(4..=4));
+        // Either end can be the one that moved
+        view.anchor = Some(messages[1].id);
+        assert_eq!(view.selection(&messages), Some(1..=4));
+        view.anchor = Some(messages[7].id);
+        assert_eq!(view.selection(&messages), Some(4..=7));
+
+        // Dropping old messages moves positions, not what is selected
+        messages.drain(..2);
+        assert_eq!(view.selection(&messages), Some(2..=5));
+        assert_eq!(view.focus_index(&messages), Some(2));
+        messages.drain(..3);
+        assert_eq!(view.selection(&messages), None);
+        view.focus = None;
+        assert_eq!(view.selection(&messages), None);
+    }
+
+    #[test]
+    fn test_line_heights() {
+        let line = Line::from(vec![Span::raw("[12:00] bob: "), Span::raw("a b c d e f")]);
+        assert_eq!(MessageBox::line_height(&line, 24), 1);
+        assert_eq!(MessageBox::line_height(&line, 12), 2);
+        assert_eq!(MessageBox::line_height(&Line::default(), 12), 1);
+        let long = Line::from("word ".repeat(20));
+        assert_eq!(
+            MessageBox::line_height(&long, 10),
+            Paragraph::new(long.clone())
+                .wrap(Wrap { trim: false })
+                .line_count(10)
+        );
     }
 
-    fn time_format(time: SystemTime) -> String {
-        let date_time: DateTime<Local> = time.into();
-        date_time.format("%H:%M:%S").to_string()
+    #[test]
+    fn test_scroll_keeps_the_focus_centred() {
+        let lines: Vec<Line> = (0..20).map(|i| Line::from(i.to_string())).collect();
+        let area = Rect::new(0, 0, 20, 12);
+        let mut view = MessageView::default();
+        // Ten rows inside the border, so the bottom is ten lines down
+        assert_eq!(MessageBox::scroll_offset(&lines, None, &mut view, area), 10);
+        view.scroll = 100;
+        assert_eq!(MessageBox::scroll_offset(&lines, None, &mut view, area), 0);
+        assert_eq!(view.scroll, 10);
+        assert_eq!(
+            MessageBox::scroll_offset(&lines, Some(8), &mut view, area),
+            3
+        );
+        assert_eq!(
+            MessageBox::scroll_offset(&lines, Some(2), &mut view, area),
+            0
+        );
+        assert_eq!(
+            MessageBox::scroll_offset(&lines, Some(19), &mut view, area),
+            10
+        );
     }
 }
@@ -0,0 +1,132 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, List, ListState},
+};
+use regex::Regex;
+
+use super::{message_box::MessageBox, picker};
+use crate::{
+    networking::{Connection, Message},
+    tui::config::{ListConfig, MessageConfig},
+};
+
+// Characters of context kept around a search match
+const CONTEXT_BEFORE: usize = 30;
+const CONTEXT_AFTER: usize = 50;
+
+pub struct MessageEntry {
+    pub connection: Connection,
+    pub conversation: String,
+    pub message: Message,
+}
+
+// Messages picked out of every conversation, newest first
+pub struct MessageList {
+    pub entries: Vec<MessageEntry>,
+    pub list_state: ListState,
+    pub highlight: Option<Regex>,
+    title: String,
+    content_style: Style,
+}
+
+impl MessageList {
+    pub fn new(title: &str, content_style: Style) -> Self {
+        Self {
+            entries: vec![],
+            list_state: ListState::default(),
+            highlight: None,
+            title: title.to_string(),
+            content_style,
+        }
+    }
+
+    pub fn update(&mut self, connections: &[Connection], filter: impl Fn(&Message) -> bool) {
+        self.entries = connections
+            .iter()
+            .flat_map(|connection| {
+                let conversation = connection.get_name();
+                connection
+                    .messages
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|m| filter(m))
+                    .map(|message| MessageEntry {
+                        connection: connection.clone(),
+                        conversation: conversation.clone(),
+                        message: message.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry.message.time));
+        if self.entries.is_empty() {
+            self.list_state.select(None);
+        } else {
+            self.list_state.select_first();
+        }
+    }
+
+    pub fn iterate_selected(&mut self, step: i32) {
+        picker::step(&mut self.list_state, self.entries.len(), step);
+    }
+
+    pub fn selected(&self) -> Option<&MessageEntry> {
+        self.list_state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    pub fn widget(&self) -> List<'static> {
+        let lines: Vec<Line> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let time: DateTime<Local> = entry.message.time.into();
+                let mut spans = vec![
+                    Span::styled(
+                        format!("[{}] ", time.format("%d %b %H:%M")),
+                        MessageConfig::time_style(),
+                    ),
+                    Span::styled(
+                        format!("{} · {}", entry.conversation, entry.message.sender_name),
+                        MessageConfig::username_style(false),
+                    ),
+                    Span::styled(" :  ", self.content_style),
+                ];
+                spans.extend(MessageBox::highlight(
+                    &self.context(&entry.message.content),
+                    self.content_style,
+                    self.highlight.as_ref(),
+                ));
+                Line::from(spans)
+            })
+            .collect();
+        let title = format!("{} ({})", self.title, self.entries.len());
+        List::new(lines)
+            .block(Block::bordered().title(title))
+            .style(ListConfig::selected_color())
+            .highlight_style(ListConfig::highlight())
+            .highlight_symbol(">>")
+    }
+
+    // Long messages are cut down to the text around the first match
+    fn context(&self, content: &str) -> String {
+        let Some(found) = self.highlight.as_ref().and_then(|h| h.find(content)) else {
+            return content.to_string();
+        };
+        let before: Vec<char> = content[..found.start()].chars().collect();
+        let after: Vec<char> = content[found.start()..].chars().collect();
+        let mut context = String::new();
+        if before.len() > CONTEXT_BEFORE {
+            context.push('…');
+        }
+        context.extend(&before[before.len().saturating_sub(CONTEXT_BEFORE)..]);
+        context.extend(after.iter().take(CONTEXT_AFTER));
+        if after.len() > CONTEXT_AFTER {
+            context.push('…');
+        }
+        context
+    }
+}
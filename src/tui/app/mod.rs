@@ -1,121 +1,1258 @@
+mod clipboard;
+mod commands;
 mod connection_list;
+mod highlight;
+mod links;
+mod markdown;
 mod message_box;
+mod message_list;
+mod notifications;
+mod picker;
+mod presence;
+mod reaction_picker;
+mod search;
+mod switcher;
 mod text_area;
+mod typing;
 use std::{
-    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
-    sync::{atomic::AtomicBool, Arc, Mutex},
-    thread,
-    time::SystemTime,
+    fs, io, mem,
+    net::TcpStream,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
+use clipboard::Clipboard;
+use commands::{Command, COMMAND_PREFIX};
 use connection_list::ConnectionList;
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
-use message_box::MessageBox;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
+use links::LinkPicker;
+use message_box::{MessageBox, MessageView};
+use message_list::MessageList;
+use notifications::Notifier;
+use presence::PresenceTracker;
 use ratatui::{
-    layout::{Constraint, Direction, Flex, Layout, Position, Rect},
-    style::{Color, Stylize},
-    widgets::Clear,
+    layout::{Constraint, Direction, Flex, Layout, Margin, Position, Rect},
+    style::Styled,
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
     Frame,
 };
+use reaction_picker::ReactionPicker;
+use search::Search;
+use switcher::Switcher;
 use text_area::TextArea;
+use typing::TypingNotifier;
 
-use crate::networking::{
-    listener::{self, Listener},
-    Connection, Message, MessageType,
+use crate::{
+    export::{self, ExportFormat},
+    keymap::{self, Action, Keymap, Mode},
+    networking::{
+        identity::Identity,
+        limits::RateLimits,
+        listener::Listener,
+        mentions::Mentions,
+        payload::MessageId,
+        presence::{Presence, Status},
+        Connection, Encryption, Message, MessageType,
+    },
+    settings::{Settings, TimeSettings},
+    theme::{self, Themes},
+    tui::config::{InputConfig, MessageConfig, PopupConfig, StatusConfig},
 };
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub enum AppState {
     Normal,
     Writing,
     Closing,
     AddingConnection,
-    ConfirmingConnection,
+    Mentions,
+    Searching,
+    SearchResults,
+    PickingLink,
+    SelectingMessage,
+    PickingReaction,
+    Switching,
+    ConfirmingPaste,
+    Help,
+}
+
+// Rows moved per notch of the mouse wheel
+const SCROLL_STEP: usize = 3;
+// Dragging the connection list's edge stops short of hiding either side
+const MIN_LIST_WIDTH: u16 = 12;
+const MIN_MESSAGES_WIDTH: u16 = 20;
+const MAX_PANES: usize = 4;
+// Panes go side by side while each gets at least this many columns, else they stack
+const MIN_PANE_WIDTH: u16 = 40;
+const NOTICE_DURATION: Duration = Duration::from_secs(5);
+
+// Where the last frame put everything, mouse events are matched against these
+#[derive(Default)]
+struct Areas {
+    connections: Rect,
+    messages: Rect,
+    input: Rect,
+    panes: Vec<Rect>,
+}
+
+// The message area can be tiled, each pane following its own conversation. The focused
+// pane lives in the list selection and message_view, the others keep theirs here
+#[derive(Default)]
+struct Pane {
+    connection: Option<Connection>,
+    view: MessageView,
+    unread_marker: Option<SystemTime>,
+}
+
+// Feedback on something the user did, kept out of the conversations themselves so it
+// never shows up in exports, search or unread counts
+struct Notice {
+    text: String,
+    error: bool,
+    since: Instant,
+}
+
+// What sending the input does
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Compose {
+    Message,
+    Reply(MessageId),
+    Edit(MessageId),
 }
 
 pub struct App<'a> {
     connection_list: ConnectionList<'a>,
-    input_widget: TextArea<'a>,
+    input_widget: TextArea,
     pub state: AppState,
-    adding_connection_popup: TextArea<'a>,
+    adding_connection_popup: TextArea,
     listener: Listener,
+    identity: Identity,
+    limits: RateLimits,
+    typing: TypingNotifier,
+    // Where typing was announced, the stop has to reach it even after switching away
+    typing_to: Option<Connection>,
+    presence: PresenceTracker,
+    mentions: Arc<Mentions>,
+    notifier: Notifier,
+    mention_list: MessageList,
+    name: Option<String>,
+    search: Search,
+    search_results: MessageList,
+    message_view: MessageView,
+    link_picker: LinkPicker,
+    hyperlinks: bool,
+    time: TimeSettings,
+    compose: Compose,
+    reaction_picker: ReactionPicker,
+    switcher: Switcher,
+    clipboard: Clipboard,
+    paste_threshold: usize,
+    // Large paste waiting for the user to decide what to do with it
+    pending_paste: Option<String>,
+    areas: Areas,
+    notice: Option<Notice>,
+    panes: Vec<Pane>,
+    focused_pane: usize,
+    // Width of the connection list once its edge has been dragged
+    list_width: Option<u16>,
+    resizing_list: bool,
+    keymap: Keymap,
+    // Whose keys the help shows, and where closing it goes back to
+    help_mode: Mode,
+    help_return: AppState,
+    help_scroll: u16,
+    themes: Themes,
 }
 
 impl App<'_> {
-    pub fn new() -> Self {
-        let mut listener = Listener::new();
+    pub fn new(settings: Settings, themes: Themes, identity: Identity) -> Self {
+        let listener = Listener::new(settings.access);
         listener.setup_thread();
+        let mentions = Mentions {
+            name: settings.name.clone(),
+            ..settings.mentions
+        };
         Self {
-            connection_list: ConnectionList::new(),
+            connection_list: ConnectionList::new(settings.connections.sort_by_activity),
             input_widget: TextArea::new("Message".to_string()),
             state: AppState::Normal,
             adding_connection_popup: TextArea::new(listener.get_ip().clone()),
             listener,
+            identity,
+            limits: settings.limits,
+            typing: TypingNotifier::new(),
+            typing_to: None,
+            presence: PresenceTracker::new(settings.presence),
+            mentions: Arc::new(mentions),
+            notifier: Notifier::new(settings.notifications),
+            mention_list: MessageList::new("Mentions", MessageConfig::mention_style()),
+            name: settings.name,
+            search: Search::new(),
+            search_results: MessageList::new("Search results", MessageConfig::text_style()),
+            message_view: MessageView::default(),
+            link_picker: LinkPicker::new(&settings.links),
+            hyperlinks: settings.links.hyperlinks,
+            time: settings.time,
+            compose: Compose::Message,
+            reaction_picker: ReactionPicker::new(),
+            switcher: Switcher::new(settings.connections.aliases),
+            clipboard: Clipboard::new(settings.clipboard),
+            paste_threshold: settings.input.paste_threshold,
+            pending_paste: None,
+            areas: Areas::default(),
+            notice: None,
+            panes: vec![Pane::default()],
+            focused_pane: 0,
+            list_width: None,
+            resizing_list: false,
+            keymap: settings.keys,
+            help_mode: Mode::Normal,
+            help_return: AppState::Normal,
+            help_scroll: 0,
+            themes,
         }
     }
     pub fn update_connection_list(&mut self) {
         self.connection_list.update(self.state == AppState::Normal);
+        // The other panes are on screen too, so what arrives there is read
+        for connection in self.pane_connections() {
+            connection.mark_read();
+        }
+    }
+
+    // Conversations shown in the panes that aren't focused
+    fn pane_connections(&self) -> impl Iterator<Item = &Connection> {
+        self.panes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.focused_pane)
+            .filter_map(|(_, pane)| pane.connection.as_ref())
+    }
+
+    pub fn tick(&mut self) -> io::Result<()> {
+        let selected = self.connection_list.selected();
+        if let Some(to) = &self.typing_to {
+            if !selected.is_some_and(|selected| selected.same_as(to)) {
+                let typing = self.typing.stop();
+                self.send_typing(typing);
+            }
+        }
+        let typing = self.typing.tick();
+        self.send_typing(typing);
+        match self.clipboard.finished() {
+            Some(Ok(target)) => self.show_notice(format!("Copied to {}", target)),
+            Some(Err(e)) => self.show_error(format!("Could not copy: {}", e)),
+            None => {}
+        }
+        if let Some(status) = self.presence.tick() {
+            self.broadcast_status(&status);
+        }
+        let unread = self
+            .connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.unread_count())
+            .sum();
+        let mut viewing: Vec<Connection> = self.pane_connections().cloned().collect();
+        viewing.extend(self.connection_list.selected());
+        self.notifier.flush(
+            self.presence.status.presence == Presence::Busy,
+            &viewing,
+            unread,
+        )
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.notifier.focused = focused;
+    }
+
+    fn broadcast_status(&mut self, status: &Status) {
+        self.connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|c| c.send_status(status));
+    }
+
+    // Bracketed paste arrives in one piece, so newlines don't send half of it
+    pub fn handle_paste(&mut self, text: String) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.state {
+            AppState::Writing => {
+                let lines = text.lines().count();
+                if self.paste_threshold > 0 && lines > self.paste_threshold {
+                    self.pending_paste = Some(text);
+                    self.state = AppState::ConfirmingPaste;
+                } else {
+                    self.insert_paste(&text);
+                }
+            }
+            AppState::AddingConnection => self
+                .adding_connection_popup
+                .insert_str(text.lines().next().unwrap_or_default().trim()),
+            AppState::Switching => {
+                self.switcher
+                    .input
+                    .insert_str(text.lines().next().unwrap_or_default().trim());
+                self.update_switcher()
+            }
+            AppState::Searching => {
+                self.search.input.insert_str(&text.replace('\n', " "));
+                if !self.search.global {
+                    self.search_as_typed();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn insert_paste(&mut self, text: &str) {
+        self.input_widget.insert_str(text);
+        let typing = self.typing.edited(!self.input_widget.content.is_empty());
+        self.send_typing(typing);
+    }
+
+    fn handle_confirming_paste_input(&mut self, key: &KeyEvent) {
+        let Some(text) = self.pending_paste.take() else {
+            self.state = AppState::Writing;
+            return;
+        };
+        match self.keymap.action(Mode::Paste, key) {
+            Some(Action::Insert) => self.insert_paste(&text),
+            Some(Action::SendSnippet) => {
+                if let Some(index) = self.connection_list.list_state.selected() {
+                    if let Some(connection) = self
+                        .connection_list
+                        .connections
+                        .lock()
+                        .unwrap()
+                        .get_mut(index)
+                    {
+                        connection.send_message(markdown::snippet(&text), MessageType::Text);
+                    }
+                }
+            }
+            Some(Action::Cancel) => {}
+            _ => {
+                self.pending_paste = Some(text);
+                return;
+            }
+        }
+        self.state = AppState::Writing
     }
 
     pub fn handle_input(&mut self, key: &KeyEvent) {
         if key.kind != KeyEventKind::Press {
             return;
         }
+        if let Some(status) = self.presence.input() {
+            self.broadcast_status(&status);
+        }
 
         match self.state {
             AppState::Normal => self.handle_normal_input(key),
             AppState::Writing => self.handle_writting_input(key),
             AppState::AddingConnection => self.handle_adding_connection_input(key),
-            AppState::ConfirmingConnection => self.handle_confirm_connection_input(key),
+            AppState::Mentions => self.handle_mentions_input(key),
+            AppState::Searching => self.handle_searching_input(key),
+            AppState::SearchResults => self.handle_search_results_input(key),
+            AppState::PickingLink => self.handle_link_picker_input(key),
+            AppState::SelectingMessage => self.handle_selecting_input(key),
+            AppState::PickingReaction => self.handle_reaction_picker_input(key),
+            AppState::Switching => self.handle_switcher_input(key),
+            AppState::ConfirmingPaste => self.handle_confirming_paste_input(key),
+            // Scroll keys scroll, cancel, confirm or the help key itself close it
+            AppState::Help => self.handle_help_input(key),
             AppState::Closing => {}
         }
     }
 
-    fn handle_confirm_connection_input(&mut self, key: &KeyEvent) {
-        todo!()
+    // Popups and selection stay keyboard only, the mouse works on the main panes
+    pub fn handle_mouse(&mut self, mouse: &MouseEvent) {
+        if self.state != AppState::Normal && self.state != AppState::Writing {
+            return;
+        }
+        let position = Position::new(mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                // The border between the list and the messages is the handle
+                let edge = self.areas.connections.right();
+                if (mouse.column + 1 == edge || mouse.column == edge)
+                    && mouse.row < self.areas.connections.bottom()
+                {
+                    self.resizing_list = true;
+                } else if self.areas.connections.contains(position) {
+                    self.click_connection(mouse.row);
+                } else if self.areas.input.contains(position) {
+                    if self.state == AppState::Normal {
+                        self.hanlde_select_connection()
+                    }
+                } else {
+                    if self.state == AppState::Writing {
+                        self.stop_writing()
+                    }
+                    if let Some(pane) = self.areas.panes.iter().position(|a| a.contains(position)) {
+                        self.focus_pane(pane)
+                    }
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.resizing_list => {
+                let total = self.areas.connections.width + self.areas.messages.width;
+                let max = total.saturating_sub(MIN_MESSAGES_WIDTH).max(MIN_LIST_WIDTH);
+                self.list_width = Some((mouse.column + 1).clamp(MIN_LIST_WIDTH, max));
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.resizing_list = false,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let up = mouse.kind == MouseEventKind::ScrollUp;
+                if let Some(pane) = self.areas.panes.iter().position(|a| a.contains(position)) {
+                    let scroll = if pane == self.focused_pane {
+                        &mut self.message_view.scroll
+                    } else {
+                        &mut self.panes[pane].view.scroll
+                    };
+                    *scroll = if up {
+                        *scroll + SCROLL_STEP
+                    } else {
+                        scroll.saturating_sub(SCROLL_STEP)
+                    };
+                } else if self.areas.connections.contains(position)
+                    && self.state == AppState::Normal
+                    && !self.connection_list.connections.lock().unwrap().is_empty()
+                {
+                    self.connection_list
+                        .iterate_selected(if up { -1 } else { 1 });
+                    self.message_view.follow_newest()
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn split_pane(&mut self) {
+        if self.panes.len() >= MAX_PANES {
+            return;
+        }
+        let (Some(index), Some(current)) = (
+            self.connection_list.list_state.selected(),
+            self.connection_list.selected(),
+        ) else {
+            return;
+        };
+        // The next conversation that isn't on screen yet, or this one again
+        let connections = self.connection_list.connections.lock().unwrap();
+        let shown = |connection: &Connection| {
+            connection.same_as(&current)
+                || self.panes.iter().any(|pane| {
+                    pane.connection
+                        .as_ref()
+                        .is_some_and(|shown| shown.same_as(connection))
+                })
+        };
+        let connection = (1..connections.len())
+            .map(|offset| &connections[(index + offset) % connections.len()])
+            .find(|connection| !shown(connection))
+            .unwrap_or(&current)
+            .clone();
+        drop(connections);
+        self.panes.insert(
+            self.focused_pane + 1,
+            Pane {
+                unread_marker: Some(connection.last_read()),
+                connection: Some(connection),
+                view: MessageView::default(),
+            },
+        );
+        self.focus_pane(self.focused_pane + 1);
+    }
+
+    fn close_pane(&mut self) {
+        if self.panes.len() < 2 {
+            return;
+        }
+        let closing = self.focused_pane;
+        self.focus_pane(if closing == 0 { 1 } else { closing - 1 });
+        self.panes.remove(closing);
+        if closing < self.focused_pane {
+            self.focused_pane -= 1;
+        }
     }
+
+    // The focused pane's state goes back into its slot and the new one's comes out
+    fn focus_pane(&mut self, index: usize) {
+        if index == self.focused_pane || index >= self.panes.len() {
+            return;
+        }
+        let current = &mut self.panes[self.focused_pane];
+        current.connection = self.connection_list.selected();
+        current.view = mem::take(&mut self.message_view);
+        current.unread_marker = self.connection_list.unread_marker;
+        self.focused_pane = index;
+        let pane = &mut self.panes[index];
+        self.message_view = mem::take(&mut pane.view);
+        if let Some(connection) = pane.connection.take() {
+            self.connection_list
+                .select_connection(&connection, pane.unread_marker);
+        }
+    }
+
+    fn step_pane(&mut self, step: i32) {
+        let count = self.panes.len() as i32;
+        self.focus_pane((self.focused_pane as i32 + step).rem_euclid(count) as usize)
+    }
+
+    // Every entry is one row and the list runs top to bottom
+    fn click_connection(&mut self, row: u16) {
+        let inner = self.areas.connections.inner(Margin::new(1, 1));
+        if row < inner.top() || row >= inner.bottom() {
+            return;
+        }
+        let index = self.connection_list.list_state.offset() + (row - inner.top()) as usize;
+        if index >= self.connection_list.connections.lock().unwrap().len() {
+            return;
+        }
+        if self.state == AppState::Writing {
+            self.stop_writing()
+        }
+        self.connection_list.list_state.select(Some(index));
+        self.message_view.follow_newest()
+    }
+
+    fn show_help(&mut self, mode: Mode) {
+        self.help_mode = mode;
+        self.help_return = self.state;
+        self.help_scroll = 0;
+        self.state = AppState::Help
+    }
+
+    fn handle_help_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::List, key) {
+            Some(Action::Next) => self.help_scroll = self.help_scroll.saturating_add(1),
+            Some(Action::Previous) => self.help_scroll = self.help_scroll.saturating_sub(1),
+            Some(Action::Cancel | Action::Confirm | Action::Help) => self.state = self.help_return,
+            _ => {}
+        }
+    }
+
+    // The mode help was opened from comes first, then every other mode and the commands
+    fn help_lines(&self) -> Vec<Line<'static>> {
+        let mut modes = vec![self.help_mode];
+        modes.extend(Mode::ALL.iter().filter(|mode| **mode != self.help_mode));
+        let mut lines = vec![];
+        for mode in modes {
+            let help = self.keymap.help(mode);
+            if help.is_empty() {
+                continue;
+            }
+            lines.push(Line::styled(
+                format!("{} mode", mode.name()),
+                StatusConfig::heading_style(),
+            ));
+            lines.extend(App::help_entries(help));
+            lines.push(Line::default());
+        }
+        lines.push(Line::styled("commands", StatusConfig::heading_style()));
+        lines.extend(App::help_entries(
+            commands::HELP
+                .iter()
+                .map(|(usage, description)| (format!("{}{}", COMMAND_PREFIX, usage), *description))
+                .collect(),
+        ));
+        lines.push(Line::default());
+        lines.push(Line::styled("identity", StatusConfig::heading_style()));
+        lines.push(Line::from(format!(
+            "fingerprint {}",
+            self.identity.fingerprint()
+        )));
+        lines
+    }
+
+    fn help_entries(entries: Vec<(String, &'static str)>) -> Vec<Line<'static>> {
+        let width = entries
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        entries
+            .into_iter()
+            .map(|(keys, description)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!(" {:>width$}  ", keys, width = width),
+                        StatusConfig::key_style(),
+                    ),
+                    Span::raw(description),
+                ])
+            })
+            .collect()
+    }
+
+    // One line at the bottom: where we are, who we are and what the keys do from here
+    fn status_bar(&self) -> Line<'static> {
+        let mut spans = vec![
+            Span::styled(
+                format!(" {} ", self.state_label()),
+                StatusConfig::state_style(),
+            ),
+            Span::raw(format!(" {} ", self.listener.get_ip())),
+            Span::raw(format!(
+                "│ {} ",
+                self.name.as_deref().unwrap_or("no name set")
+            )),
+        ];
+        if let Some(index) = self.connection_list.list_state.selected() {
+            if let Some(connection) = self.connection_list.connections.lock().unwrap().get(index) {
+                spans.push(Span::raw(format!("│ {} ", connection.get_name())));
+                let encryption = connection.encryption();
+                let style = match encryption {
+                    Encryption::Plain => StatusConfig::unencrypted_style(),
+                };
+                spans.push(Span::styled(format!("{} ", encryption.label()), style));
+            }
+        }
+        spans.push(Span::raw("│"));
+        // Feedback on the last thing done takes the place of the hints for a while
+        if let Some(notice) = self
+            .notice
+            .as_ref()
+            .filter(|notice| notice.since.elapsed() < NOTICE_DURATION)
+        {
+            let style = match notice.error {
+                true => MessageConfig::error_style(),
+                false => StatusConfig::key_style(),
+            };
+            spans.push(Span::styled(format!(" {} ", notice.text), style));
+            return Line::from(spans).style(StatusConfig::bar_style());
+        }
+        for (key, description) in self.hints() {
+            spans.push(Span::styled(format!(" {}", key), StatusConfig::key_style()));
+            spans.push(Span::raw(format!(" {} ", description)));
+        }
+        Line::from(spans).style(StatusConfig::bar_style())
+    }
+
+    fn state_label(&self) -> &'static str {
+        match (self.state, self.compose) {
+            (AppState::Normal, _) => "NORMAL",
+            (AppState::Writing, Compose::Reply(_)) => "REPLY",
+            (AppState::Writing, Compose::Edit(_)) => "EDIT",
+            (AppState::Writing, Compose::Message) => "WRITING",
+            (AppState::Closing, _) => "CLOSING",
+            (AppState::AddingConnection, _) => "CONNECT",
+            (AppState::Mentions, _) => "MENTIONS",
+            (AppState::Searching, _) => "SEARCH",
+            (AppState::SearchResults, _) => "RESULTS",
+            (AppState::PickingLink, _) => "LINKS",
+            (AppState::SelectingMessage, _) => "SELECT",
+            (AppState::PickingReaction, _) => "REACT",
+            (AppState::Switching, _) => "SWITCH",
+            (AppState::ConfirmingPaste, _) => "PASTE",
+            (AppState::Help, _) => "HELP",
+        }
+    }
+
+    // The few keys worth knowing in the current state, the help has the rest
+    fn hints(&self) -> Vec<(String, &'static str)> {
+        let (mode, actions): (Mode, &[Action]) = match self.state {
+            AppState::Normal => (
+                Mode::Normal,
+                &[
+                    Action::Compose,
+                    Action::AddConnection,
+                    Action::SelectMessages,
+                    Action::Search,
+                    Action::Help,
+                    Action::Quit,
+                ],
+            ),
+            AppState::Writing
+            | AppState::Searching
+            | AppState::AddingConnection
+            | AppState::Switching => (
+                Mode::Writing,
+                &[Action::Confirm, Action::Cancel, Action::Help],
+            ),
+            AppState::SelectingMessage => (
+                Mode::Selecting,
+                &[
+                    Action::Reply,
+                    Action::React,
+                    Action::ToggleRange,
+                    Action::Copy,
+                    Action::Cancel,
+                    Action::Help,
+                ],
+            ),
+            AppState::Mentions
+            | AppState::SearchResults
+            | AppState::PickingLink
+            | AppState::PickingReaction => (Mode::List, &[Action::Confirm, Action::Cancel]),
+            AppState::ConfirmingPaste => (
+                Mode::Paste,
+                &[Action::Insert, Action::SendSnippet, Action::Cancel],
+            ),
+            AppState::Help => (
+                Mode::List,
+                &[Action::Next, Action::Previous, Action::Cancel],
+            ),
+            AppState::Closing => return vec![],
+        };
+        actions
+            .iter()
+            .filter_map(|action| Some((self.keymap.key_for(mode, *action)?, action.description())))
+            .collect()
+    }
+
     fn handle_adding_connection_input(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc => self.state = AppState::Normal,
-            KeyCode::Char(c) => self.adding_connection_popup.enter_char(c),
-            KeyCode::Backspace => self.adding_connection_popup.delete_current_char(),
-            KeyCode::Left => self.adding_connection_popup.move_cursor_left(),
-            KeyCode::Right => self.adding_connection_popup.move_cursor_right(),
-            KeyCode::Enter => self.handle_add_connection(),
-            KeyCode::Delete => {
-                self.adding_connection_popup.move_cursor_right();
-                self.adding_connection_popup.delete_current_char()
+        match self.keymap.action(Mode::Writing, key) {
+            Some(Action::Cancel) => self.state = AppState::Normal,
+            Some(Action::Confirm) => self.handle_add_connection(),
+            Some(Action::Help) => self.show_help(Mode::Writing),
+            action => App::edit_text(&mut self.adding_connection_popup, action, key),
+        }
+    }
+
+    // Cursor movement and deletion shared by every text input, unbound keys are typed
+    fn edit_text(input: &mut TextArea, action: Option<Action>, key: &KeyEvent) {
+        match action {
+            Some(Action::Left) => input.move_cursor_left(),
+            Some(Action::Right) => input.move_cursor_right(),
+            Some(Action::LineStart) => input.reset_cursor(),
+            Some(Action::LineEnd) => input.move_cursor_end(),
+            Some(Action::Backspace) => input.delete_current_char(),
+            Some(Action::Delete) => {
+                input.move_cursor_right();
+                input.delete_current_char()
+            }
+            Some(Action::DeleteToEnd) => input.delete_to_end(),
+            Some(Action::ClearInput) => input.clear_input(),
+            Some(_) => {}
+            None => {
+                if let Some(c) = keymap::typed(key) {
+                    input.enter_char(c)
+                }
             }
-            _ => {}
         }
     }
     fn handle_add_connection(&mut self) {
-        if let Some(connection) =
-            TcpStream::connect(self.adding_connection_popup.content.clone()).ok()
-        {
+        if let Ok(mut stream) = TcpStream::connect(self.adding_connection_popup.content.clone()) {
+            if let Err(e) = self.identity.answer_challenge(&mut stream) {
+                self.show_error(format!("Could not connect: {}", e));
+                return;
+            }
+            self.add_connection(stream);
+            self.adding_connection_popup.clear_input();
+            self.connection_list.list_state.select_last();
+        }
+    }
+    fn add_connection(&mut self, stream: TcpStream) {
+        let mut connection = Connection::new(stream)
+            .with_limits(self.limits)
+            .with_mentions(Arc::clone(&self.mentions))
+            .with_notify(self.notifier.default_level(), self.notifier.hook());
+        if let Some(name) = &self.name {
+            connection.send_name(name);
+        }
+        connection.send_status(&self.presence.status);
+        Connection::register_listener(Arc::new(Mutex::new(connection.clone())));
+        self.connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .push(connection);
+    }
+    fn handle_normal_input(&mut self, key: &KeyEvent) {
+        let Some(action) = self.keymap.action(Mode::Normal, key) else {
+            return;
+        };
+        match action {
+            Action::Cancel if self.search.pattern.is_some() => self.clear_search(),
+            Action::Cancel | Action::Quit => self.closing_sequence(),
+            Action::Next => {
+                self.connection_list.iterate_selected(1);
+                self.message_view.follow_newest()
+            }
+            Action::Previous => {
+                self.connection_list.iterate_selected(-1);
+                self.message_view.follow_newest()
+            }
+            Action::ClearInput => self.input_widget.clear_input(),
+            Action::AddConnection => self.state = AppState::AddingConnection,
+            Action::NextUnread => {
+                self.connection_list.select_next_unread();
+                self.message_view.follow_newest()
+            }
+            Action::SwitchConversation => {
+                let current = self.connection_list.selected();
+                self.switcher.start(
+                    &self.connection_list.connections.lock().unwrap(),
+                    current.as_ref(),
+                );
+                self.state = AppState::Switching
+            }
+            Action::Mentions => {
+                self.mention_list
+                    .update(&self.connection_list.connections.lock().unwrap(), |m| {
+                        m.is_mention
+                    });
+                self.state = AppState::Mentions
+            }
+            Action::Search => {
+                self.search.start(false);
+                self.state = AppState::Searching
+            }
+            Action::SearchAll => {
+                self.search.start(true);
+                self.state = AppState::Searching
+            }
+            Action::NextMatch => self.step_search(1),
+            Action::PreviousMatch => self.step_search(-1),
+            Action::ToggleSort => {
+                self.connection_list.sort_by_activity = !self.connection_list.sort_by_activity
+            }
+            Action::ToggleRaw => self.message_view.raw = !self.message_view.raw,
+            Action::CopyCode => self.copy_code_block(),
+            Action::SelectMessages => {
+                self.message_view.anchor = None;
+                self.message_view.focus = self
+                    .connection_list
+                    .with_selected_messages(|m| m.last().map(|m| m.id))
+                    .flatten();
+                if self.message_view.focus.is_some() {
+                    self.state = AppState::SelectingMessage
+                }
+            }
+            Action::OpenLinks => {
+                let picker = &mut self.link_picker;
+                if self
+                    .connection_list
+                    .with_selected_messages(|m| picker.update(m))
+                    .is_some()
+                {
+                    self.state = AppState::PickingLink
+                }
+            }
+            Action::Compose => self.hanlde_select_connection(),
+            Action::SplitPane => self.split_pane(),
+            Action::ClosePane => self.close_pane(),
+            Action::NextPane => self.step_pane(1),
+            Action::PreviousPane => self.step_pane(-1),
+            Action::Help => self.show_help(Mode::Normal),
+            _ => {}
+        }
+    }
+
+    fn handle_mentions_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::List, key) {
+            Some(Action::Cancel) => self.state = AppState::Normal,
+            Some(Action::Next) => self.mention_list.iterate_selected(1),
+            Some(Action::Previous) => self.mention_list.iterate_selected(-1),
+            Some(Action::Help) => self.show_help(Mode::List),
+            Some(Action::Confirm) => {
+                if let Some(entry) = self.mention_list.selected() {
+                    let (connection, id) = (entry.connection.clone(), entry.message.id);
+                    self.open_message(&connection, id);
+                }
+                self.state = AppState::Normal
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_selecting_input(&mut self, key: &KeyEvent) {
+        let view = &self.message_view;
+        let (Some(id), Some((focus, count))) = (
+            view.focus,
             self.connection_list
+                .with_selected_messages(|m| Some((view.focus_index(m)?, m.len())))
+                .flatten(),
+        ) else {
+            self.state = AppState::Normal;
+            return;
+        };
+        let Some(action) = self.keymap.action(Mode::Selecting, key) else {
+            return;
+        };
+        match action {
+            Action::Cancel if self.message_view.anchor.is_some() => self.message_view.anchor = None,
+            Action::Cancel => {
+                self.message_view.follow_newest();
+                self.state = AppState::Normal
+            }
+            // Starts or drops a range, which then grows with the focus
+            Action::ToggleRange => {
+                self.message_view.anchor = match self.message_view.anchor {
+                    Some(_) => None,
+                    None => Some(id),
+                }
+            }
+            Action::Copy => self.copy_selection(false),
+            Action::CopyWithHeaders => self.copy_selection(true),
+            Action::Previous => self.focus_message(focus.saturating_sub(1)),
+            Action::Next => self.focus_message((focus + 1).min(count.saturating_sub(1))),
+            Action::First => self.focus_message(0),
+            Action::Last => self.focus_message(count.saturating_sub(1)),
+            Action::CopyCode => self.copy_code_block(),
+            Action::Reply => self.start_reply(focus),
+            Action::Edit => self.start_edit(focus),
+            Action::React => self.state = AppState::PickingReaction,
+            Action::Help => self.show_help(Mode::Selecting),
+            Action::Expand => {
+                let expanded = &mut self.message_view.expanded;
+                if !expanded.remove(&id) {
+                    expanded.insert(id);
+                }
+            }
+            Action::Retract => {
+                if let Some(index) = self.connection_list.list_state.selected() {
+                    if let Some(connection) = self
+                        .connection_list
+                        .connections
+                        .lock()
+                        .unwrap()
+                        .get_mut(index)
+                    {
+                        connection.send_retract(id);
+                    }
+                }
+            }
+            // Jumps to the message the focused one replies to
+            Action::JumpToParent => {
+                let parent = self
+                    .connection_list
+                    .with_selected_messages(|m| {
+                        let parent = m.get(focus)?.reply_to?;
+                        m.iter().any(|m| m.id == parent).then_some(parent)
+                    })
+                    .flatten();
+                if parent.is_some() {
+                    self.message_view.focus = parent;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn focus_message(&mut self, index: usize) {
+        let view = &mut self.message_view;
+        self.connection_list
+            .with_selected_messages(|m| view.focus_on(m, Some(index)));
+    }
+
+    // Typing goes to the query, the arrows and other untyped list keys move the selection
+    fn handle_switcher_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::Writing, key) {
+            Some(Action::Cancel) => self.state = AppState::Normal,
+            Some(Action::Confirm) => {
+                if let Some(connection) = self.switcher.selected().cloned() {
+                    self.connection_list
+                        .select_connection(&connection, Some(connection.last_read()));
+                    self.message_view.follow_newest();
+                }
+                self.state = AppState::Normal
+            }
+            Some(Action::Help) => self.show_help(Mode::Writing),
+            None if keymap::typed(key).is_none() => match self.keymap.action(Mode::List, key) {
+                Some(Action::Next) => self.switcher.iterate_selected(1),
+                Some(Action::Previous) => self.switcher.iterate_selected(-1),
+                _ => {}
+            },
+            action => {
+                App::edit_text(&mut self.switcher.input, action, key);
+                self.update_switcher()
+            }
+        }
+    }
+
+    fn update_switcher(&mut self) {
+        let current = self.connection_list.selected();
+        self.switcher.update(
+            &self.connection_list.connections.lock().unwrap(),
+            current.as_ref(),
+        );
+    }
+
+    fn handle_reaction_picker_input(&mut self, key: &KeyEvent) {
+        let emoji = match (self.keymap.action(Mode::List, key), key.code) {
+            (Some(Action::Cancel), _) => {
+                self.state = AppState::SelectingMessage;
+                return;
+            }
+            (Some(Action::Next), _) => {
+                self.reaction_picker.iterate_selected(1);
+                return;
+            }
+            (Some(Action::Previous), _) => {
+                self.reaction_picker.iterate_selected(-1);
+                return;
+            }
+            (Some(Action::Help), _) => {
+                self.show_help(Mode::List);
+                return;
+            }
+            (Some(Action::Confirm), _) => self.reaction_picker.selected(),
+            // The digits shown next to each reaction pick it straight away
+            (None, KeyCode::Char(c)) if c.is_ascii_digit() => self.reaction_picker.select_digit(c),
+            _ => return,
+        };
+        let id = self.message_view.focus;
+        let index = self.connection_list.list_state.selected();
+        if let (Some(emoji), Some(id), Some(index)) = (emoji, id, index) {
+            if let Some(connection) = self
+                .connection_list
                 .connections
                 .lock()
                 .unwrap()
-                .push(Connection::new(connection));
-            self.adding_connection_popup.clear_input();
-            self.connection_list.list_state.select_last();
+                .get_mut(index)
+            {
+                connection.send_reaction(id, emoji);
+            }
         }
+        self.state = AppState::SelectingMessage
     }
-    fn handle_normal_input(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc | KeyCode::Char('q') => self.closing_sequence(),
-            KeyCode::Down | KeyCode::Char('j') => self.connection_list.iterate_selected(1),
-            KeyCode::Up | KeyCode::Char('k') => self.connection_list.iterate_selected(-1),
-            KeyCode::Char('c') => self.input_widget.clear_input(),
-            KeyCode::Char('a') => self.state = AppState::AddingConnection,
-            KeyCode::Char('i') | KeyCode::Tab | KeyCode::Enter => self.hanlde_select_connection(),
+
+    fn start_reply(&mut self, index: usize) {
+        let Some((id, title)) = self
+            .connection_list
+            .with_selected_messages(|m| {
+                m.get(index).map(|m| {
+                    (
+                        m.id,
+                        format!(
+                            "Reply to {}: {}",
+                            m.sender_name,
+                            MessageBox::preview(&m.content)
+                        ),
+                    )
+                })
+            })
+            .flatten()
+        else {
+            return;
+        };
+        self.compose = Compose::Reply(id);
+        self.input_widget.set_title(title);
+        self.message_view.follow_newest();
+        self.state = AppState::Writing
+    }
+
+    // Loads one of our own messages into the input
+    fn start_edit(&mut self, index: usize) {
+        let Some((id, content)) = self
+            .connection_list
+            .with_selected_messages(|m| {
+                m.get(index)
+                    .filter(|m| m.sent_by_self && !m.deleted && m.message_type == MessageType::Text)
+                    .map(|m| (m.id, m.content.clone()))
+            })
+            .flatten()
+        else {
+            return;
+        };
+        self.compose = Compose::Edit(id);
+        self.input_widget.clear_input();
+        for c in content.chars() {
+            self.input_widget.enter_char(c);
+        }
+        self.input_widget
+            .set_title("Edit message, empty it to delete".to_string());
+        self.message_view.follow_newest();
+        self.state = AppState::Writing
+    }
+
+    fn reset_compose(&mut self) {
+        if let Compose::Edit(_) = self.compose {
+            self.input_widget.clear_input();
+        }
+        self.compose = Compose::Message;
+        self.input_widget.set_title("Message".to_string());
+    }
+
+    fn handle_link_picker_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::List, key) {
+            Some(Action::Cancel) => self.state = AppState::Normal,
+            Some(Action::Next) => self.link_picker.iterate_selected(1),
+            Some(Action::Previous) => self.link_picker.iterate_selected(-1),
+            Some(Action::Help) => self.show_help(Mode::List),
+            Some(Action::Confirm) => {
+                if let Err(e) = self.link_picker.open_selected() {
+                    self.show_error(format!("Could not open link: {}", e));
+                }
+                self.state = AppState::Normal
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_searching_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::Writing, key) {
+            Some(Action::Cancel) => {
+                self.clear_search();
+                self.state = AppState::Normal
+            }
+            Some(Action::Confirm) if self.search.global => {
+                self.search.update_pattern();
+                self.search_results.highlight = self.search.pattern.clone();
+                let search = &self.search;
+                self.search_results
+                    .update(&self.connection_list.connections.lock().unwrap(), |m| {
+                        search.is_match(m)
+                    });
+                self.state = AppState::SearchResults
+            }
+            Some(Action::Confirm) => self.state = AppState::Normal,
+            Some(Action::Help) => self.show_help(Mode::Writing),
+            action => App::edit_text(&mut self.search.input, action, key),
+        }
+        if self.state == AppState::Searching && !self.search.global {
+            self.search_as_typed();
+        }
+    }
+
+    // Searching the current conversation is incremental
+    fn search_as_typed(&mut self) {
+        self.search.update_pattern();
+        self.message_view.search = self.search.pattern.clone();
+        let (search, view) = (&mut self.search, &mut self.message_view);
+        self.connection_list
+            .with_selected_messages(|m| view.focus_on(m, search.find(m)));
+    }
+
+    fn handle_search_results_input(&mut self, key: &KeyEvent) {
+        match self.keymap.action(Mode::List, key) {
+            Some(Action::Cancel) => {
+                self.clear_search();
+                self.state = AppState::Normal
+            }
+            Some(Action::Next) => self.search_results.iterate_selected(1),
+            Some(Action::Previous) => self.search_results.iterate_selected(-1),
+            Some(Action::Help) => self.show_help(Mode::List),
+            Some(Action::Confirm) => {
+                if let Some(entry) = self.search_results.selected() {
+                    let (connection, id) = (entry.connection.clone(), entry.message.id);
+                    self.open_message(&connection, id);
+                    // Keep highlighting in the conversation so n/N keep working there
+                    self.message_view.search = self.search.pattern.clone();
+                    self.search.jump_to(self.message_view.focus);
+                }
+                self.state = AppState::Normal
+            }
             _ => {}
         }
     }
 
-    fn handle_create_connection(&mut self) {
-        todo!()
+    fn clear_search(&mut self) {
+        self.search.clear();
+        self.message_view.search = None;
+        self.message_view.follow_newest();
+    }
+
+    fn step_search(&mut self, step: i32) {
+        if self.search.pattern.is_none() {
+            return;
+        }
+        let (search, view) = (&mut self.search, &mut self.message_view);
+        self.connection_list
+            .with_selected_messages(|m| view.focus_on(m, search.step(m, step)));
+    }
+
+    // Copies the last code block of the focused message, or of the newest message with one
+    fn copy_code_block(&mut self) {
+        let focus = self.message_view.focus;
+        let Some(block) = self
+            .connection_list
+            .with_selected_messages(|messages| {
+                let blocks = |m: &Message| markdown::code_blocks(&m.content).pop();
+                match focus {
+                    Some(id) => messages.iter().find(|m| m.id == id).and_then(blocks),
+                    None => messages.iter().rev().find_map(blocks),
+                }
+            })
+            .flatten()
+        else {
+            return;
+        };
+        let what = format!("code block ({} lines)", block.lines().count());
+        self.copy(&block, &what);
+    }
+
+    // The selected messages, with time and sender or just what they said
+    fn copy_selection(&mut self, with_headers: bool) {
+        let view = &self.message_view;
+        let Some(text) = self
+            .connection_list
+            .with_selected_messages(|messages| {
+                let range = view.selection(messages)?;
+                Some(App::copied_lines(
+                    &messages[range],
+                    &self.time,
+                    with_headers,
+                ))
+            })
+            .flatten()
+        else {
+            return;
+        };
+        let what = match text.len() {
+            1 => "1 message".to_string(),
+            count => format!("{} messages", count),
+        };
+        self.copy(&text.join("\n"), &what);
+        self.message_view.anchor = None;
+    }
+
+    // Headers are in the configured timezone but always with the full date, whatever the
+    // message view shows
+    fn copied_lines(messages: &[Message], time: &TimeSettings, with_headers: bool) -> Vec<String> {
+        messages
+            .iter()
+            .filter(|m| with_headers || !m.deleted)
+            .map(|m| {
+                let content = if m.deleted {
+                    "message deleted"
+                } else {
+                    &m.content
+                };
+                if with_headers {
+                    format!(
+                        "[{}] {}: {}",
+                        time.timezone.at(m.time).format("%Y-%m-%d %H:%M:%S"),
+                        m.sender_name,
+                        content
+                    )
+                } else {
+                    content.to_string()
+                }
+            })
+            .collect()
+    }
+
+    fn copy(&mut self, text: &str, what: &str) {
+        match self.clipboard.copy(text) {
+            Ok(Some(target)) => self.show_notice(format!("Copied {} to {}", what, target)),
+            Ok(None) => self.show_notice(format!("Copying {}…", what)),
+            Err(e) => self.show_error(format!("Could not copy: {}", e)),
+        }
+    }
+
+    // Selects the conversation and scrolls to the message
+    fn open_message(&mut self, connection: &Connection, id: MessageId) {
+        let index = self
+            .connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|c| c.same_as(connection));
+        self.connection_list.list_state.select(index);
+        self.message_view.focus = self
+            .connection_list
+            .with_selected_messages(|m| m.iter().any(|m| m.id == id).then_some(id))
+            .flatten();
     }
 
     fn hanlde_select_connection(&mut self) {
@@ -126,50 +1263,208 @@ impl App<'_> {
     }
 
     fn handle_writting_input(&mut self, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Esc => self.state = AppState::Normal,
-            KeyCode::Char(c) => self.input_widget.enter_char(c),
-            KeyCode::Backspace => self.input_widget.delete_current_char(),
-            KeyCode::Left => self.input_widget.move_cursor_left(),
-            KeyCode::Right => self.input_widget.move_cursor_right(),
-            KeyCode::Enter => self.handle_message_send(),
-            KeyCode::Delete => {
-                self.input_widget.move_cursor_right();
-                self.input_widget.delete_current_char()
+        let previous_content = self.input_widget.content.clone();
+        match self.keymap.action(Mode::Writing, key) {
+            Some(Action::Cancel) => self.stop_writing(),
+            Some(Action::Confirm) => self.handle_message_send(),
+            Some(Action::Help) => self.show_help(Mode::Writing),
+            action => App::edit_text(&mut self.input_widget, action, key),
+        }
+        if self.state == AppState::Writing && self.input_widget.content != previous_content {
+            let typing = self.typing.edited(!self.input_widget.content.is_empty());
+            self.send_typing(typing);
+        }
+    }
+
+    fn stop_writing(&mut self) {
+        let typing = self.typing.stop();
+        self.send_typing(typing);
+        self.reset_compose();
+        self.state = AppState::Normal
+    }
+
+    fn send_typing(&mut self, typing: Option<bool>) {
+        let Some(typing) = typing else {
+            return;
+        };
+        let to = typing.then(|| self.connection_list.selected()).flatten();
+        if let Some(mut previous) = mem::replace(&mut self.typing_to, to.clone()) {
+            if !to.as_ref().is_some_and(|to| to.same_as(&previous)) {
+                previous.send_typing(false);
             }
-            _ => {}
+        }
+        if let Some(mut to) = to {
+            to.send_typing(true);
         }
     }
 
     fn handle_message_send(&mut self) {
-        todo!();
+        let Some(index) = self.connection_list.list_state.selected() else {
+            return;
+        };
+        let content = self.input_widget.content.clone();
+        self.input_widget.clear_input();
+        let compose = self.compose;
+        self.reset_compose();
+        // Edits are sent as typed, an emptied message is deleted
+        if let Compose::Edit(id) = compose {
+            if let Some(connection) = self
+                .connection_list
+                .connections
+                .lock()
+                .unwrap()
+                .get_mut(index)
+            {
+                if content.is_empty() {
+                    connection.send_retract(id);
+                } else {
+                    connection.send_edit(id, content);
+                }
+            }
+            let typing = self.typing.stop();
+            self.send_typing(typing);
+            return;
+        }
+        // A doubled prefix escapes the command, "//shrug" is sent as "/shrug"
+        if content.starts_with(COMMAND_PREFIX) && !content[1..].starts_with(COMMAND_PREFIX) {
+            let typing = self.typing.stop();
+            self.send_typing(typing);
+            self.handle_command(&content, index);
+            return;
+        }
+        let content = content
+            .strip_prefix(COMMAND_PREFIX)
+            .map_or(content.clone(), str::to_string);
+        if let Some(connection) = self
+            .connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .get_mut(index)
+        {
+            match compose {
+                Compose::Reply(parent) => connection.send_reply(content, parent),
+                _ => connection.send_message(content, MessageType::Text),
+            }
+        }
+        self.typing.sent();
+        self.typing_to = None;
     }
+
+    fn handle_command(&mut self, input: &str, index: usize) {
+        let status = match input.parse::<Command>() {
+            Ok(Command::Presence(presence)) => self.presence.set_presence(presence),
+            Ok(Command::Status(text)) => self.presence.set_text(text),
+            Ok(Command::Notify(level)) => {
+                if let Some(connection) =
+                    self.connection_list.connections.lock().unwrap().get(index)
+                {
+                    connection.set_notify_level(level);
+                }
+                None
+            }
+            Ok(Command::Theme(name)) => {
+                self.switch_theme(name);
+                None
+            }
+            Ok(Command::Export(format, path)) => {
+                let connection = self
+                    .connection_list
+                    .connections
+                    .lock()
+                    .unwrap()
+                    .get(index)
+                    .cloned();
+                if let Some(connection) = connection {
+                    self.export_conversation(&connection, format, path);
+                }
+                None
+            }
+            Err(e) => {
+                self.show_error(e);
+                None
+            }
+        };
+        if let Some(status) = status {
+            self.broadcast_status(&status);
+        }
+    }
+    fn switch_theme(&mut self, name: Option<String>) {
+        let Some(name) = name else {
+            self.show_notice(format!(
+                "Themes: {} (using {})",
+                self.themes.names().join(", "),
+                theme::current_name().unwrap_or_default()
+            ));
+            return;
+        };
+        match self.themes.load(&name) {
+            Ok(loaded) => {
+                theme::set(loaded);
+                self.show_notice(format!("Switched to the {} theme", name));
+            }
+            Err(e) => self.show_error(e),
+        }
+    }
+
+    fn export_conversation(
+        &mut self,
+        connection: &Connection,
+        format: ExportFormat,
+        path: Option<PathBuf>,
+    ) {
+        let conversation = connection.get_name();
+        let path = path.unwrap_or_else(|| export::default_path(&conversation, format));
+        let content = export::export(&conversation, &connection.messages.lock().unwrap(), format);
+        match fs::write(&path, content) {
+            Ok(()) => self.show_notice(format!("Exported to {}", path.display())),
+            Err(e) => self.show_error(format!("Could not export to {}: {}", path.display(), e)),
+        }
+    }
+
+    fn show_notice(&mut self, text: String) {
+        self.notice = Some(Notice {
+            text,
+            error: false,
+            since: Instant::now(),
+        });
+    }
+
+    fn show_error(&mut self, text: String) {
+        self.notice = Some(Notice {
+            text,
+            error: true,
+            since: Instant::now(),
+        });
+    }
+
     fn closing_sequence(&mut self) {
-        //        self.connection_list
-        //           .connections
-        //          .lock()
-        //         .unwrap()
-        //        .iter()
-        //       .for_each(|&c| c.disconnect());
+        self.connection_list
+            .connections
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|c| c.disconnect());
         self.state = AppState::Closing
     }
     pub fn render(&mut self, frame: &mut Frame) {
-        let list_constraint = if self.state == AppState::Writing {
+        let list_constraint = if let Some(width) = self.list_width {
+            Constraint::Length(width)
+        } else if self.state == AppState::Writing {
             Constraint::Percentage(15)
         } else {
             Constraint::Percentage(30)
         };
         if let Some(stream) = self.listener.pop() {
-            self.connection_list
-                .connections
-                .lock()
-                .unwrap()
-                .push(Connection::new(stream));
+            self.add_connection(stream);
         }
+        let [main_area, status_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+        frame.render_widget(Paragraph::new(self.status_bar()), status_area);
         let main_layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints(vec![list_constraint, Constraint::Fill(1)])
-            .split(frame.area());
+            .split(main_area);
         frame.render_stateful_widget(
             self.connection_list.list.clone(),
             main_layout[0],
@@ -177,35 +1472,87 @@ impl App<'_> {
         );
         let text_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Percentage(100), Constraint::Min(6)])
+            .constraints(vec![
+                Constraint::Percentage(100),
+                Constraint::Length(1),
+                Constraint::Min(6),
+            ])
             .split(main_layout[1]);
+        let pane_areas = Layout::default()
+            .direction(
+                if text_layout[0].width / self.panes.len() as u16 >= MIN_PANE_WIDTH {
+                    Direction::Horizontal
+                } else {
+                    Direction::Vertical
+                },
+            )
+            .constraints(vec![Constraint::Fill(1); self.panes.len()])
+            .split(text_layout[0]);
+        self.areas = Areas {
+            connections: main_layout[0],
+            messages: text_layout[0],
+            input: text_layout[2],
+            panes: pane_areas.to_vec(),
+        };
         frame.render_widget(
             self.input_widget
-                .get_widget(self.state == AppState::Writing),
-            text_layout[1],
+                .get_widget(self.state == AppState::Writing, text_layout[2]),
+            text_layout[2],
         );
-        if let Some(index) = self.connection_list.list_state.selected() {
+        let tiled = self.panes.len() > 1;
+        let selected = self.connection_list.selected();
+        for (i, pane) in self.panes.iter_mut().enumerate() {
+            let (connection, view, unread_marker) = if i == self.focused_pane {
+                (
+                    selected.as_ref(),
+                    &mut self.message_view,
+                    self.connection_list.unread_marker,
+                )
+            } else {
+                (pane.connection.as_ref(), &mut pane.view, pane.unread_marker)
+            };
+            let area = pane_areas[i];
+            let Some(connection) = connection else {
+                frame.render_widget(Block::bordered().title("Messages"), area);
+                continue;
+            };
+            let messages = connection.messages.lock().unwrap();
+            let title = if tiled {
+                connection.get_name()
+            } else {
+                "Messages".to_string()
+            };
             frame.render_widget(
                 MessageBox::new(
-                    &self
-                        .connection_list
-                        .connections
-                        .lock()
-                        .unwrap()
-                        .get(index)
-                        .unwrap()
-                        .messages
-                        .lock()
-                        .unwrap(),
+                    &messages,
+                    unread_marker,
+                    view,
+                    area,
+                    title,
+                    tiled && i == self.focused_pane,
+                    &self.time,
                 ),
-                text_layout[0],
+                area,
             );
+            if self.hyperlinks {
+                links::hyperlink(
+                    frame.buffer_mut(),
+                    area.inner(Margin::new(1, 1)),
+                    &links::collect(&messages),
+                );
+            }
+        }
+        if let Some(connection) = selected {
+            if connection.is_peer_typing() {
+                frame.render_widget(
+                    Paragraph::new(format!(" {} is typing…", connection.get_name()))
+                        .style(MessageConfig::typing_style()),
+                    text_layout[1],
+                );
+            }
         }
         if self.state == AppState::Writing {
-            frame.set_cursor_position(Position::new(
-                text_layout[1].x + self.input_widget.character_index as u16 + 1,
-                text_layout[1].y + 1,
-            ));
+            frame.set_cursor_position(self.input_widget.cursor_position(text_layout[2]));
         } else if self.state == AppState::AddingConnection {
             let area = App::centered_popup(
                 frame.area(),
@@ -214,13 +1561,121 @@ impl App<'_> {
             );
             frame.render_widget(Clear, area);
             frame.render_widget(
-                self.adding_connection_popup
-                    .get_widget(true)
-                    .bg(Color::Black),
+                App::popup(self.adding_connection_popup.get_widget(true, area)),
                 area,
             );
+        } else if self.state == AppState::Searching {
+            frame.render_widget(Clear, text_layout[2]);
+            frame.render_widget(
+                self.search.input.get_widget(true, text_layout[2]),
+                text_layout[2],
+            );
+            frame.set_cursor_position(self.search.input.cursor_position(text_layout[2]));
+        } else if self.state == AppState::Mentions || self.state == AppState::SearchResults {
+            let list = if self.state == AppState::Mentions {
+                &mut self.mention_list
+            } else {
+                &mut self.search_results
+            };
+            let area = App::centered_popup(
+                frame.area(),
+                Constraint::Percentage(80),
+                Constraint::Percentage(60),
+            );
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(App::popup(list.widget()), area, &mut list.list_state);
+        } else if self.state == AppState::PickingReaction {
+            let area =
+                App::centered_popup(frame.area(), Constraint::Length(30), Constraint::Length(12));
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(
+                App::popup(self.reaction_picker.widget()),
+                area,
+                &mut self.reaction_picker.list_state,
+            );
+        } else if self.state == AppState::Switching {
+            let height = self.switcher.len().clamp(1, 10) as u16 + 2;
+            let area = App::centered_popup(
+                frame.area(),
+                Constraint::Percentage(60),
+                Constraint::Length(height + 3),
+            );
+            let [input_area, list_area] =
+                Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(area);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                App::popup(self.switcher.input.get_widget(true, input_area)),
+                input_area,
+            );
+            frame.render_stateful_widget(
+                App::popup(self.switcher.widget()),
+                list_area,
+                &mut self.switcher.list_state,
+            );
+            frame.set_cursor_position(self.switcher.input.cursor_position(input_area));
+        } else if self.state == AppState::ConfirmingPaste {
+            let lines = self
+                .pending_paste
+                .as_ref()
+                .map_or(0, |text| text.lines().count());
+            let choices = App::help_entries(self.keymap.help(Mode::Paste));
+            let width = choices.iter().map(Line::width).max().unwrap_or(0) + 3;
+            let area = App::centered_popup(
+                frame.area(),
+                Constraint::Length(width.max(24) as u16),
+                Constraint::Length(choices.len() as u16 + 2),
+            );
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                App::popup(
+                    Paragraph::new(choices)
+                        .block(Block::bordered().title(format!("Paste of {} lines", lines)))
+                        .style(InputConfig::selected_color()),
+                ),
+                area,
+            );
+        } else if self.state == AppState::Help {
+            let lines = self.help_lines();
+            let width = lines.iter().map(Line::width).max().unwrap_or(0) + 3;
+            let height = (lines.len() + 2).min(frame.area().height as usize * 4 / 5);
+            // Can't scroll past the last line
+            self.help_scroll = self
+                .help_scroll
+                .min(lines.len().saturating_sub(height.saturating_sub(2)) as u16);
+            let area = App::centered_popup(
+                frame.area(),
+                Constraint::Length(width as u16),
+                Constraint::Length(height as u16),
+            );
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                App::popup(
+                    Paragraph::new(lines)
+                        .block(Block::bordered().title("Help"))
+                        .scroll((self.help_scroll, 0)),
+                ),
+                area,
+            );
+        } else if self.state == AppState::PickingLink {
+            let area = App::centered_popup(
+                frame.area(),
+                Constraint::Percentage(70),
+                Constraint::Percentage(50),
+            );
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(
+                App::popup(self.link_picker.widget()),
+                area,
+                &mut self.link_picker.list_state,
+            );
         }
     }
+    // The popup background fills in whatever the widget leaves unset
+    fn popup<W: Styled<Item = W>>(widget: W) -> W {
+        let style = PopupConfig::style().patch(widget.style());
+        widget.set_style(style)
+    }
+
     fn centered_popup(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
         let [area] = Layout::horizontal([horizontal])
             .flex(Flex::Center)
@@ -229,3 +1684,232 @@ impl App<'_> {
         area
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{env, net::TcpListener, process};
+
+    use super::*;
+    use crate::{
+        networking::{identity::Identity, payload, test::eventually},
+        settings::Zone,
+        theme::ColorDepth,
+    };
+
+    // An app with this many conversations, the far ends are kept so the sockets stay open
+    fn app_with(count: usize) -> (App<'static>, Vec<Connection>, Vec<TcpStream>) {
+        let identity = Identity::load_or_create(
+            &env::temp_dir().join(format!("tui_chat_app_identity_{}", process::id())),
+        )
+        .unwrap();
+        let themes = Themes::new(PathBuf::from("/nonexistent"), ColorDepth::Truecolor);
+        let mut app = App::new(Settings::default(), themes, identity);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peers = vec![];
+        let mut connections = vec![];
+        for _ in 0..count {
+            let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            peers.push(listener.accept().unwrap().0);
+            connections.push(Connection::new(stream));
+        }
+        *app.connection_list.connections.lock().unwrap() = connections.clone();
+        app.connection_list.list_state.select(Some(0));
+        app.update_connection_list();
+        (app, connections, peers)
+    }
+
+    // Which conversation each pane shows, the focused one going by the list selection
+    fn shown(app: &App, connections: &[Connection]) -> Vec<usize> {
+        let index = |c: &Connection| connections.iter().position(|o| o.same_as(c)).unwrap();
+        (0..app.panes.len())
+            .map(|i| match i == app.focused_pane {
+                true => index(&app.connection_list.selected().unwrap()),
+                false => index(app.panes[i].connection.as_ref().unwrap()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_opens_the_next_conversation() {
+        let (mut app, connections, _peers) = app_with(3);
+        app.split_pane();
+        assert_eq!(shown(&app, &connections), [0, 1]);
+        assert_eq!(app.focused_pane, 1);
+        app.focus_pane(0);
+        app.split_pane();
+        assert_eq!(shown(&app, &connections), [0, 2, 1]);
+        assert_eq!(app.focused_pane, 1);
+        // Nothing left that isn't shown, so the current one is doubled up
+        app.split_pane();
+        assert_eq!(shown(&app, &connections), [0, 2, 2, 1]);
+        app.split_pane();
+        assert_eq!(app.panes.len(), MAX_PANES);
+    }
+
+    #[test]
+    fn test_close_and_step_keep_focus_in_range() {
+        let (mut app, connections, _peers) = app_with(3);
+        app.split_pane();
+        app.split_pane();
+        assert_eq!(shown(&app, &connections), [0, 1, 2]);
+        app.step_pane(1);
+        assert_eq!(app.focused_pane, 0);
+        app.step_pane(-1);
+        assert_eq!(app.focused_pane, 2);
+
+        app.focus_pane(1);
+        app.close_pane();
+        assert_eq!(shown(&app, &connections), [0, 2]);
+        assert_eq!(app.focused_pane, 0);
+        app.close_pane();
+        assert_eq!(shown(&app, &connections), [2]);
+        assert_eq!(app.focused_pane, 0);
+        app.close_pane();
+        assert_eq!(app.panes.len(), 1);
+    }
+
+    #[test]
+    fn test_feedback_stays_out_of_conversations() {
+        let (mut app, connections, _peers) = app_with(1);
+        let path = env::temp_dir().join(format!("tui_chat_export_{}.md", process::id()));
+        app.handle_command(&format!("/export md {}", path.display()), 0);
+        app.handle_command("/theme no-such-theme", 0);
+        app.handle_command("/no-such-command", 0);
+
+        assert!(connections[0].messages.lock().unwrap().is_empty());
+        assert!(app.notice.as_ref().is_some_and(|notice| notice.error));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("Conversation with"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_typing_stops_where_it_was_announced() {
+        let (mut app, _connections, peers) = app_with(2);
+        let peers: Vec<_> = peers
+            .into_iter()
+            .map(|stream| {
+                let peer = Connection::new(stream);
+                Connection::register_listener(Arc::new(Mutex::new(peer.clone())));
+                peer
+            })
+            .collect();
+        let typing = |peer: &Connection, typing: bool| {
+            eventually(|| (peer.is_peer_typing() == typing).then_some(())).is_some()
+        };
+        app.state = AppState::Writing;
+        app.insert_paste("hi");
+        assert!(typing(&peers[0], true));
+
+        app.connection_list.list_state.select(Some(1));
+        app.tick().unwrap();
+        assert!(typing(&peers[0], false));
+        assert!(!peers[1].is_peer_typing());
+    }
+
+    #[test]
+    fn test_every_pane_counts_as_read() {
+        let (mut app, connections, _peers) = app_with(3);
+        app.split_pane();
+        // Only conversations marked read from here on have a later time
+        let before = SystemTime::now();
+        app.update_connection_list();
+        assert!(connections[0].last_read() >= before);
+        assert!(connections[1].last_read() >= before);
+        assert!(connections[2].last_read() < before);
+    }
+
+    fn descriptions(app: &App) -> Vec<&'static str> {
+        app.hints()
+            .into_iter()
+            .map(|(_, description)| description)
+            .collect()
+    }
+
+    #[test]
+    fn test_status_follows_the_state() {
+        let (mut app, _connections, _peers) = app_with(1);
+        assert_eq!(app.state_label(), "NORMAL");
+        // The shortest key bound to an action is the one hinted at
+        assert_eq!(app.hints()[0], ("i".to_string(), "write a message"));
+        assert!(descriptions(&app).contains(&"quit"));
+
+        app.state = AppState::Writing;
+        assert_eq!(app.state_label(), "WRITING");
+        app.compose = Compose::Reply(payload::new_id());
+        assert_eq!(app.state_label(), "REPLY");
+        assert_eq!(descriptions(&app), ["confirm", "back", "show these keys"]);
+
+        app.state = AppState::Closing;
+        assert_eq!(app.state_label(), "CLOSING");
+        assert!(app.hints().is_empty());
+    }
+
+    #[test]
+    fn test_status_bar_shows_the_selected_connection() {
+        let (app, connections, _peers) = app_with(1);
+        let status = app.status_bar().to_string();
+        assert!(status.contains(&connections[0].get_name()));
+        assert!(status.contains(Encryption::Plain.label()));
+    }
+
+    #[test]
+    fn test_help_starts_with_the_mode_it_was_opened_from() {
+        let (mut app, _connections, _peers) = app_with(1);
+        app.state = AppState::Writing;
+        app.show_help(Mode::Writing);
+        let lines: Vec<String> = app.help_lines().iter().map(Line::to_string).collect();
+        assert_eq!(lines[0], "writing mode");
+        let headings: Vec<&str> = lines
+            .iter()
+            .filter(|line| line.ends_with(" mode"))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(headings.len(), Mode::ALL.len());
+        assert!(lines.contains(&"commands".to_string()));
+        assert_eq!(
+            lines.last(),
+            Some(&format!("fingerprint {}", app.identity.fingerprint()))
+        );
+
+        app.handle_help_input(&KeyEvent::from(KeyCode::Esc));
+        assert!(app.state == AppState::Writing);
+    }
+
+    #[test]
+    fn test_copied_lines() {
+        let message = |sender_name: &str, content: &str, deleted: bool| Message {
+            id: payload::new_id(),
+            reply_to: None,
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(90),
+            sent_by_self: false,
+            system: false,
+            sender_name: sender_name.to_string(),
+            message_type: MessageType::Text,
+            content: content.to_string(),
+            is_mention: false,
+            edited: None,
+            deleted,
+            reactions: vec![],
+        };
+        let messages = [
+            message("alice", "hi", false),
+            message("bob", "gone", true),
+            message("alice", "there", false),
+        ];
+        let time = TimeSettings {
+            timezone: Zone::try_from("+05:30".to_string()).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(App::copied_lines(&messages, &time, false), ["hi", "there"]);
+        assert_eq!(
+            App::copied_lines(&messages, &time, true),
+            [
+                "[1970-01-01 05:31:30] alice: hi",
+                "[1970-01-01 05:31:30] bob: message deleted",
+                "[1970-01-01 05:31:30] alice: there",
+            ]
+        );
+    }
+}
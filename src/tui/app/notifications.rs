@@ -0,0 +1,105 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use chrono::Local;
+use crossterm::{execute, terminal::SetTitle};
+
+use crate::{
+    networking::{
+        notify::{Notification, NotifyHook, NotifyLevel},
+        Connection,
+    },
+    settings::{DesktopNotification, NotificationSettings},
+};
+
+const APP_TITLE: &str = "tui_chat";
+const MAX_BODY_LEN: usize = 120;
+
+// Reader threads only queue notifications, they are written out between
+// frames so the escape sequences never interleave with a draw
+pub struct Notifier {
+    settings: NotificationSettings,
+    queue: Arc<Mutex<VecDeque<Notification>>>,
+    title_unread: Option<usize>,
+    pub focused: bool,
+}
+
+impl Notifier {
+    pub fn new(settings: NotificationSettings) -> Self {
+        Self {
+            settings,
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            title_unread: None,
+            focused: true,
+        }
+    }
+
+    pub fn default_level(&self) -> NotifyLevel {
+        self.settings.default_level
+    }
+
+    pub fn hook(&self) -> NotifyHook {
+        let queue = Arc::clone(&self.queue);
+        Arc::new(move |notification| queue.lock().unwrap().push_back(notification))
+    }
+
+    pub fn flush(&mut self, busy: bool, viewing: &[Connection], unread: usize) -> io::Result<()> {
+        let pending: Vec<Notification> = self.queue.lock().unwrap().drain(..).collect();
+        let mut out = io::stdout();
+        if self.settings.window_title && self.title_unread != Some(unread) {
+            let title = if unread > 0 {
+                format!("({}) {}", unread, APP_TITLE)
+            } else {
+                APP_TITLE.to_string()
+            };
+            execute!(out, SetTitle(title))?;
+            self.title_unread = Some(unread);
+        }
+
+        let quiet_hours = self
+            .settings
+            .quiet_hours
+            .is_some_and(|quiet| quiet.contains(Local::now().time()));
+        if busy || quiet_hours {
+            return Ok(());
+        }
+        // Nothing to tell the user about the conversations they are looking at
+        let pending: Vec<Notification> = pending
+            .into_iter()
+            .filter(|n| !(self.focused && viewing.iter().any(|c| c.same_as(&n.connection))))
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if self.settings.bell {
+            write!(out, "\x07")?;
+        }
+        for notification in pending {
+            let title = Notifier::clean(&notification.conversation);
+            let body = Notifier::clean(&notification.message.content);
+            match self.settings.desktop {
+                DesktopNotification::Off => {}
+                DesktopNotification::Osc9 => write!(out, "\x1b]9;{}: {}\x07", title, body)?,
+                DesktopNotification::Osc777 => write!(
+                    out,
+                    "\x1b]777;notify;{};{}\x07",
+                    title.replace(';', ","),
+                    body.replace(';', ",")
+                )?,
+            }
+        }
+        out.flush()
+    }
+
+    // Peer supplied text ends up inside an escape sequence, so control
+    // characters must not make it through
+    fn clean(text: &str) -> String {
+        text.chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_BODY_LEN)
+            .collect()
+    }
+}
@@ -0,0 +1,28 @@
+use ratatui::widgets::ListState;
+
+// Moves a popup list's selection by `step`, wrapping around at either end
+pub fn step(list_state: &mut ListState, len: usize, step: i32) {
+    if len == 0 {
+        return;
+    }
+    let current = list_state.selected().unwrap_or(0) as i32;
+    list_state.select(Some((current + step).rem_euclid(len as i32) as usize));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_step_wraps_around() {
+        let mut list_state = ListState::default();
+        step(&mut list_state, 0, 1);
+        assert_eq!(list_state.selected(), None);
+        step(&mut list_state, 3, -1);
+        assert_eq!(list_state.selected(), Some(2));
+        step(&mut list_state, 3, 1);
+        assert_eq!(list_state.selected(), Some(0));
+        step(&mut list_state, 3, 7);
+        assert_eq!(list_state.selected(), Some(1));
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    networking::presence::{Presence, Status},
+    settings::PresenceSettings,
+};
+
+// Owns our own status, every method returns the status to broadcast when it changed
+pub struct PresenceTracker {
+    pub status: Status,
+    auto_away: bool,
+    auto_away_after: Option<Duration>,
+    last_input: Instant,
+}
+
+impl PresenceTracker {
+    pub fn new(settings: PresenceSettings) -> Self {
+        Self {
+            status: Status {
+                presence: Presence::Online,
+                text: settings.status,
+            },
+            auto_away: false,
+            auto_away_after: (settings.auto_away_minutes > 0)
+                .then(|| Duration::from_secs(settings.auto_away_minutes * 60)),
+            last_input: Instant::now(),
+        }
+    }
+
+    pub fn input(&mut self) -> Option<Status> {
+        self.last_input = Instant::now();
+        if !self.auto_away {
+            return None;
+        }
+        self.set_presence(Presence::Online)
+    }
+
+    pub fn tick(&mut self) -> Option<Status> {
+        let idle_for = self.auto_away_after?;
+        if self.status.presence != Presence::Online || self.last_input.elapsed() < idle_for {
+            return None;
+        }
+        let status = self.set_presence(Presence::Away);
+        self.auto_away = true;
+        status
+    }
+
+    pub fn set_presence(&mut self, presence: Presence) -> Option<Status> {
+        self.auto_away = false;
+        if self.status.presence == presence {
+            return None;
+        }
+        self.status.presence = presence;
+        Some(self.status.clone())
+    }
+
+    pub fn set_text(&mut self, text: String) -> Option<Status> {
+        if self.status.text == text {
+            return None;
+        }
+        self.status.text = text;
+        Some(self.status.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tracker(auto_away_minutes: u64) -> PresenceTracker {
+        PresenceTracker::new(PresenceSettings {
+            auto_away_minutes,
+            status: "lunch".to_string(),
+        })
+    }
+
+    fn idle(tracker: &mut PresenceTracker, minutes: u64) {
+        tracker.last_input = Instant::now() - Duration::from_secs(minutes * 60);
+    }
+
+    #[test]
+    fn test_idle_goes_away_and_input_comes_back() {
+        let mut tracker = tracker(10);
+        assert_eq!(tracker.tick(), None);
+        idle(&mut tracker, 10);
+        assert_eq!(tracker.tick().map(|s| s.presence), Some(Presence::Away));
+        assert_eq!(tracker.tick(), None);
+        let back = tracker.input().unwrap();
+        assert_eq!(back.presence, Presence::Online);
+        assert_eq!(back.text, "lunch");
+        assert_eq!(tracker.input(), None);
+    }
+
+    #[test]
+    fn test_chosen_presence_is_left_alone() {
+        let mut tracker = tracker(10);
+        assert_eq!(
+            tracker.set_presence(Presence::Busy).map(|s| s.presence),
+            Some(Presence::Busy)
+        );
+        assert_eq!(tracker.set_presence(Presence::Busy), None);
+        idle(&mut tracker, 60);
+        assert_eq!(tracker.tick(), None);
+        // Away by choice isn't undone by typing
+        tracker.set_presence(Presence::Away);
+        assert_eq!(tracker.input(), None);
+        assert_eq!(tracker.status.presence, Presence::Away);
+    }
+
+    #[test]
+    fn test_zero_minutes_never_goes_away() {
+        let mut tracker = tracker(0);
+        idle(&mut tracker, 600);
+        assert_eq!(tracker.tick(), None);
+        assert_eq!(tracker.set_text("lunch".to_string()), None);
+        assert_eq!(
+            tracker.set_text("back soon".to_string()).map(|s| s.text),
+            Some("back soon".to_string())
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use ratatui::{
+    text::Line,
+    widgets::{Block, List, ListState},
+};
+
+use super::picker;
+use crate::{networking::reactions::SHORTCODES, tui::config::ListConfig};
+
+pub struct ReactionPicker {
+    pub list_state: ListState,
+}
+
+impl ReactionPicker {
+    pub fn new() -> Self {
+        Self {
+            list_state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn iterate_selected(&mut self, step: i32) {
+        picker::step(&mut self.list_state, SHORTCODES.len(), step);
+    }
+
+    pub fn selected(&self) -> Option<&'static str> {
+        self.list_state
+            .selected()
+            .and_then(|i| SHORTCODES.get(i))
+            .map(|(_, emoji)| *emoji)
+    }
+
+    // 1-9 then 0 pick the first ten entries directly
+    pub fn select_digit(&mut self, digit: char) -> Option<&'static str> {
+        let index = (digit.to_digit(10)? as usize + 9) % 10;
+        self.list_state
+            .select(Some(index).filter(|i| *i < SHORTCODES.len()));
+        self.selected()
+    }
+
+    pub fn widget(&self) -> List<'static> {
+        let lines: Vec<Line> = SHORTCODES
+            .iter()
+            .enumerate()
+            .map(|(i, (code, emoji))| {
+                Line::from(format!("{}  {}  :{}:", (i + 1) % 10, emoji, code))
+            })
+            .collect();
+        List::new(lines)
+            .block(Block::bordered().title("React"))
+            .style(ListConfig::selected_color())
+            .highlight_style(ListConfig::highlight())
+            .highlight_symbol(">>")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_selection_wraps() {
+        let mut picker = ReactionPicker::new();
+        assert_eq!(picker.selected(), Some(SHORTCODES[0].1));
+        picker.iterate_selected(-1);
+        assert_eq!(
+            picker.selected(),
+            SHORTCODES.last().map(|(_, emoji)| *emoji)
+        );
+        picker.iterate_selected(1);
+        assert_eq!(picker.selected(), Some(SHORTCODES[0].1));
+    }
+
+    #[test]
+    fn test_digits_pick_entries() {
+        let mut picker = ReactionPicker::new();
+        assert_eq!(picker.select_digit('1'), Some(SHORTCODES[0].1));
+        assert_eq!(picker.select_digit('0'), Some(SHORTCODES[9].1));
+        assert_eq!(picker.list_state.selected(), Some(9));
+        assert_eq!(picker.select_digit('x'), None);
+        // A letter leaves the selection alone
+        assert_eq!(picker.selected(), Some(SHORTCODES[9].1));
+    }
+}
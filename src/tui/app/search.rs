@@ -0,0 +1,163 @@
+use regex::Regex;
+
+use super::text_area::TextArea;
+use crate::networking::{payload::MessageId, Message};
+
+pub struct Search {
+    pub input: TextArea,
+    pub global: bool,
+    pub pattern: Option<Regex>,
+    // The message the last jump landed on
+    current: Option<MessageId>,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            input: TextArea::new("Search".to_string()),
+            global: false,
+            pattern: None,
+            current: None,
+        }
+    }
+
+    pub fn start(&mut self, global: bool) {
+        self.clear();
+        self.global = global;
+        self.input = TextArea::new(if global { "Search all" } else { "Search" }.to_string());
+    }
+
+    pub fn clear(&mut self) {
+        self.input.clear_input();
+        self.pattern = None;
+        self.current = None;
+    }
+
+    // Plain text, case-insensitive
+    pub fn update_pattern(&mut self) {
+        self.pattern = (!self.input.content.is_empty())
+            .then(|| Regex::new(&format!("(?i){}", regex::escape(&self.input.content))).unwrap());
+    }
+
+    pub fn is_match(&self, message: &Message) -> bool {
+        self.pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_match(&message.content))
+    }
+
+    // Lands on the newest match
+    pub fn find(&mut self, messages: &[Message]) -> Option<usize> {
+        let found = messages.iter().rposition(|m| self.is_match(m));
+        self.current = found.map(|i| messages[i].id);
+        found
+    }
+
+    pub fn jump_to(&mut self, id: Option<MessageId>) {
+        self.current = id;
+    }
+
+    // Positive steps go to older messages, negative ones to newer, wrapping around
+    pub fn step(&mut self, messages: &[Message], step: i32) -> Option<usize> {
+        let matches: Vec<usize> = (0..messages.len())
+            .filter(|i| self.is_match(&messages[*i]))
+            .collect();
+        // Starting over from the newest match when the last one was dropped
+        let current = self
+            .current
+            .and_then(|id| messages.iter().position(|m| m.id == id));
+        let next = match current {
+            None => matches.last(),
+            Some(current) if step > 0 => matches
+                .iter()
+                .rev()
+                .find(|i| **i < current)
+                .or(matches.last()),
+            Some(current) => matches.iter().find(|i| **i > current).or(matches.first()),
+        };
+        let Some(next) = next.copied() else {
+            return current;
+        };
+        self.current = Some(messages[next].id);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::networking::{payload, MessageType};
+
+    fn messages(contents: &[&str]) -> Vec<Message> {
+        contents
+            .iter()
+            .map(|content| Message {
+                id: payload::new_id(),
+                reply_to: None,
+                time: SystemTime::now(),
+                sent_by_self: false,
+                system: false,
+                sender_name: "bob".to_string(),
+                message_type: MessageType::Text,
+                content: content.to_string(),
+                is_mention: false,
+                edited: None,
+                deleted: false,
+                reactions: vec![],
+            })
+            .collect()
+    }
+
+    fn search(query: &str) -> Search {
+        let mut search = Search::new();
+        search.input.content = query.to_string();
+        search.update_pattern();
+        search
+    }
+
+    #[test]
+    fn test_find_lands_on_the_newest_match() {
+        let messages = messages(&["Cat", "dog", "a cat", "bird"]);
+        assert_eq!(search("CAT").find(&messages), Some(2));
+        assert_eq!(search("fish").find(&messages), None);
+        assert_eq!(search("").find(&messages), None);
+        // Taken literally, not as a regex
+        assert_eq!(search("c.t").find(&messages), None);
+    }
+
+    #[test]
+    fn test_step_wraps_around_the_matches() {
+        let messages = messages(&["cat", "dog", "cat", "bird", "cat"]);
+        let mut search = search("cat");
+        assert_eq!(search.step(&messages, 1), Some(4));
+        assert_eq!(search.step(&messages, 1), Some(2));
+        assert_eq!(search.step(&messages, 1), Some(0));
+        assert_eq!(search.step(&messages, 1), Some(4));
+        assert_eq!(search.step(&messages, -1), Some(0));
+        assert_eq!(search.step(&messages, -1), Some(2));
+        // Stepping from a message that isn't a match goes to the nearest one that way
+        search.jump_to(Some(messages[3].id));
+        assert_eq!(search.step(&messages, -1), Some(4));
+        search.jump_to(Some(messages[3].id));
+        assert_eq!(search.step(&messages, 1), Some(2));
+        // Nothing matching leaves the position alone
+        search.input.content = "fish".to_string();
+        search.update_pattern();
+        assert_eq!(search.step(&messages, 1), Some(2));
+    }
+
+    #[test]
+    fn test_step_follows_the_message_not_its_position() {
+        let mut messages = messages(&["cat", "dog", "cat", "bird", "cat"]);
+        let mut search = search("cat");
+        search.step(&messages, 1);
+        assert_eq!(search.step(&messages, 1), Some(2));
+        // Still on the oldest match once the first message is dropped, so back wraps around
+        messages.drain(..1);
+        assert_eq!(search.step(&messages, 1), Some(3));
+        // The one we were on is gone, so it starts over from the newest
+        messages.pop();
+        assert_eq!(search.step(&messages, 1), Some(1));
+    }
+}
@@ -0,0 +1,242 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use ratatui::{
+    text::{Line, Span},
+    widgets::{Block, List, ListState},
+};
+
+use super::{connection_list::ConnectionList, picker, text_area::TextArea};
+use crate::{networking::Connection, tui::config::ListConfig};
+
+// Jumps to a conversation by typing a few letters of its name, alias or address
+pub struct Switcher {
+    pub input: TextArea,
+    pub list_state: ListState,
+    matches: Vec<Connection>,
+    aliases: HashMap<String, String>,
+}
+
+impl Switcher {
+    pub fn new(aliases: HashMap<String, String>) -> Self {
+        Self {
+            input: TextArea::new("Switch to".to_string()),
+            list_state: ListState::default(),
+            matches: vec![],
+            aliases,
+        }
+    }
+
+    pub fn start(&mut self, connections: &[Connection], current: Option<&Connection>) {
+        self.input.clear_input();
+        self.update(connections, current);
+    }
+
+    // Best match first, ties go to unread then recently active conversations. The one
+    // already open sinks below its peers so enter on an empty query goes somewhere new
+    pub fn update(&mut self, connections: &[Connection], current: Option<&Connection>) {
+        let query = self.input.content.trim().to_lowercase();
+        let mut matches: Vec<_> = connections
+            .iter()
+            .filter_map(|connection| {
+                let score = self
+                    .candidates(connection)
+                    .iter()
+                    .filter_map(|candidate| fuzzy_score(&query, candidate))
+                    .max()?;
+                let is_current = current.is_some_and(|c| c.same_as(connection));
+                Some((
+                    (
+                        Reverse(score),
+                        is_current,
+                        connection.unread_count() == 0,
+                        Reverse(connection.last_activity()),
+                    ),
+                    connection.clone(),
+                ))
+            })
+            .collect();
+        matches.sort_by_key(|(key, _)| *key);
+        self.matches = matches.into_iter().map(|(_, c)| c).collect();
+        self.list_state
+            .select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn candidates(&self, connection: &Connection) -> Vec<String> {
+        let mut candidates = vec![connection.get_name()];
+        candidates.extend(self.alias(connection).map(str::to_string));
+        candidates.extend(connection.address().map(|address| address.to_string()));
+        candidates
+    }
+
+    // An alias for the exact address wins over one for the whole host
+    fn alias(&self, connection: &Connection) -> Option<&str> {
+        let address = connection.address()?;
+        self.aliases
+            .get(&address.to_string())
+            .or_else(|| self.aliases.get(&address.ip().to_string()))
+            .map(String::as_str)
+    }
+
+    pub fn iterate_selected(&mut self, step: i32) {
+        picker::step(&mut self.list_state, self.matches.len(), step);
+    }
+
+    pub fn selected(&self) -> Option<&Connection> {
+        self.matches.get(self.list_state.selected()?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn widget(&self) -> List<'static> {
+        let lines: Vec<Line> = self
+            .matches
+            .iter()
+            .map(|connection| {
+                let mut line = ConnectionList::get_line(connection);
+                if let Some(alias) = self.alias(connection) {
+                    line.push_span(Span::raw(format!(" ({})", alias)));
+                }
+                if let Some(address) = connection.address() {
+                    line.push_span(Span::styled(
+                        format!("  {}", address),
+                        ListConfig::status_style(),
+                    ));
+                }
+                line
+            })
+            .collect();
+        List::new(lines)
+            .block(Block::bordered().title(format!("{} conversations", self.matches.len())))
+            .style(ListConfig::selected_color())
+            .highlight_style(ListConfig::highlight())
+            .highlight_symbol(">>")
+    }
+}
+
+// The query's characters have to show up in order. Runs of them and hits at the start of
+// a word count for more, so "bo" ranks "Bob" over "Jacob Olsen". Expects a lowercase query
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous = None;
+    for c in query.chars() {
+        let found = (start..candidate.len()).find(|i| candidate[*i] == c)?;
+        score += 1;
+        if found > 0 && previous == Some(found - 1) {
+            score += 4;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        start = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::{TcpListener, TcpStream},
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::networking::{payload, Message, MessageType};
+
+    fn connection(name: &str, listener: &TcpListener) -> (Connection, TcpStream) {
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let connection = Connection::new(stream);
+        *connection.name.lock().unwrap() = name.to_string();
+        (connection, listener.accept().unwrap().0)
+    }
+
+    fn receive(connection: &Connection, time: SystemTime) {
+        connection.messages.lock().unwrap().push(Message {
+            id: payload::new_id(),
+            reply_to: None,
+            time,
+            sent_by_self: false,
+            system: false,
+            sender_name: connection.get_name(),
+            message_type: MessageType::Text,
+            content: "hi".to_string(),
+            is_mention: false,
+            edited: None,
+            deleted: false,
+            reactions: vec![],
+        });
+    }
+
+    fn names(switcher: &mut Switcher) -> Vec<String> {
+        (0..switcher.len())
+            .map(|i| {
+                switcher.list_state.select(Some(i));
+                switcher.selected().unwrap().get_name()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("ba", "abc"), None);
+        assert_eq!(fuzzy_score("abc", "ab"), None);
+        // A run at the start of a word gets both bonuses
+        assert_eq!(fuzzy_score("bo", "Bob"), Some(1 + 3 + 1 + 4));
+        assert_eq!(fuzzy_score("bo", "Jacob Olsen"), Some(1 + 1 + 3));
+        assert_eq!(fuzzy_score("jo", "jacob olsen"), Some(1 + 3 + 1));
+        assert!(fuzzy_score("al", "Alice") > fuzzy_score("al", "Pascal"));
+        assert!(fuzzy_score("10.7", "10.0.0.7:9000").is_some());
+    }
+
+    #[test]
+    fn test_ties_go_to_unread_then_recent_with_the_current_one_last() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (current, _a) = connection("current", &listener);
+        let (quiet, _b) = connection("quiet", &listener);
+        let (unread, _c) = connection("unread", &listener);
+        let (recent, _d) = connection("recent", &listener);
+        // Only messages after the last read count as unread
+        receive(&unread, SystemTime::now() + Duration::from_secs(60));
+        receive(&recent, SystemTime::now() - Duration::from_secs(60));
+        receive(&current, SystemTime::now() + Duration::from_secs(60));
+        let connections = [current.clone(), quiet, recent, unread];
+
+        let mut switcher = Switcher::new(HashMap::new());
+        switcher.start(&connections, Some(&current));
+        assert_eq!(
+            names(&mut switcher),
+            ["unread", "recent", "quiet", "current"]
+        );
+        switcher.input.content = "qu".to_string();
+        switcher.update(&connections, Some(&current));
+        assert_eq!(names(&mut switcher), ["quiet"]);
+        assert_eq!(switcher.list_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn test_aliases_are_matched() {
+        // Separate listeners, so the two have different addresses
+        let listeners = [0, 1].map(|_| TcpListener::bind("127.0.0.1:0").unwrap());
+        let (alice, _a) = connection("127.0.0.1", &listeners[0]);
+        let (bob, _b) = connection("bob", &listeners[1]);
+        let exact = bob.address().unwrap().to_string();
+        let aliases = HashMap::from([
+            ("127.0.0.1".to_string(), "Alice".to_string()),
+            (exact, "Robert".to_string()),
+        ]);
+        let connections = [alice, bob];
+
+        let mut switcher = Switcher::new(aliases);
+        switcher.input.content = "alice".to_string();
+        switcher.update(&connections, None);
+        assert_eq!(names(&mut switcher), ["127.0.0.1"]);
+        switcher.input.content = "robert".to_string();
+        switcher.update(&connections, None);
+        assert_eq!(names(&mut switcher), ["bob"]);
+    }
+}
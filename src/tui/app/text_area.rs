@@ -1,23 +1,28 @@
-use ratatui::widgets::{Block, Paragraph, Wrap};
+use ratatui::{
+    layout::{Margin, Position, Rect},
+    text::Line,
+    widgets::{Block, Paragraph},
+};
 
 use crate::tui::config::InputConfig;
 
-pub struct TextArea<'a> {
+pub struct TextArea {
     pub content: String,
     pub character_index: usize,
-    widget: Paragraph<'a>,
     title: String,
 }
 
-impl TextArea<'_> {
+impl TextArea {
     pub fn new(title: String) -> Self {
         Self {
             content: String::new(),
             character_index: 0,
-            widget: Paragraph::new(vec![]),
             title,
         }
     }
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
     fn clamp_cursor(&self, index: usize) -> usize {
         index.clamp(0, self.content.chars().count())
     }
@@ -28,6 +33,9 @@ impl TextArea<'_> {
     pub fn move_cursor_right(&mut self) {
         self.character_index = self.clamp_cursor(self.character_index.saturating_add(1));
     }
+    pub fn move_cursor_end(&mut self) {
+        self.character_index = self.content.chars().count();
+    }
     pub fn reset_cursor(&mut self) {
         self.character_index = 0;
     }
@@ -43,6 +51,11 @@ impl TextArea<'_> {
         self.content.insert(index, chr);
         self.move_cursor_right()
     }
+    pub fn insert_str(&mut self, text: &str) {
+        let index = self.byte_index();
+        self.content.insert_str(index, text);
+        self.character_index = self.clamp_cursor(self.character_index + text.chars().count());
+    }
     pub fn delete_current_char(&mut self) {
         if self.character_index == 0 {
             return;
@@ -55,19 +68,114 @@ impl TextArea<'_> {
         self.move_cursor_left();
     }
 
+    pub fn delete_to_end(&mut self) {
+        let index = self.byte_index();
+        self.content.truncate(index);
+    }
+
     pub fn clear_input(&mut self) {
         self.content.clear();
         self.reset_cursor();
     }
 
-    pub fn get_widget(&self, writable: bool) -> Paragraph {
-        Paragraph::new(self.content.as_str())
-            .style(if writable {
-                InputConfig::selected_color()
-            } else {
-                InputConfig::unselected_color()
-            })
-            .block(Block::bordered().title(self.title.clone()))
-            .wrap(Wrap { trim: true })
+    // The cursor's row and column within the wrapped content
+    fn cursor(&self, width: u16) -> (usize, usize) {
+        let before: String = self.content.chars().take(self.character_index).collect();
+        let rows = wrap(&before, width);
+        let column = rows[rows.len() - 1].chars().count();
+        // A full row pushes the cursor onto the next one, like the next character would be
+        match column >= width.max(1) as usize {
+            true => (rows.len(), 0),
+            false => (rows.len() - 1, column),
+        }
+    }
+
+    // Rows scrolled off the top so the cursor stays in view
+    fn scroll(&self, inner: Rect) -> usize {
+        let (row, _) = self.cursor(inner.width);
+        (row + 1).saturating_sub(inner.height as usize)
+    }
+
+    pub fn cursor_position(&self, area: Rect) -> Position {
+        let inner = area.inner(Margin::new(1, 1));
+        let (row, column) = self.cursor(inner.width);
+        Position::new(
+            inner.x + column as u16,
+            inner.y + row.saturating_sub(self.scroll(inner)) as u16,
+        )
+    }
+
+    pub fn get_widget(&self, writable: bool, area: Rect) -> Paragraph<'_> {
+        let inner = area.inner(Margin::new(1, 1));
+        Paragraph::new(
+            wrap(&self.content, inner.width)
+                .into_iter()
+                .map(Line::from)
+                .collect::<Vec<_>>(),
+        )
+        .style(if writable {
+            InputConfig::selected_color()
+        } else {
+            InputConfig::unselected_color()
+        })
+        .block(Block::bordered().title(self.title.clone()))
+        .scroll((self.scroll(inner) as u16, 0))
+    }
+}
+
+// Breaks at newlines and at the width, one column per character as the cursor counts them
+fn wrap(text: &str, width: u16) -> Vec<String> {
+    let width = width.max(1) as usize;
+    let mut rows = vec![String::new()];
+    let mut columns = 0;
+    for c in text.chars() {
+        if c == '\n' || columns == width {
+            rows.push(String::new());
+            columns = 0;
+        }
+        if c != '\n' {
+            rows.last_mut().unwrap().push(c);
+            columns += 1;
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod test {
+    use ratatui::{buffer::Buffer, widgets::Widget};
+
+    use super::*;
+
+    fn typed(text: &str) -> TextArea {
+        let mut input = TextArea::new(String::new());
+        input.insert_str(text);
+        input
+    }
+
+    #[test]
+    fn test_cursor_follows_newlines_and_wrapping() {
+        // Leaves four columns and two rows inside the border
+        let area = Rect::new(0, 0, 6, 4);
+        assert_eq!(typed("ab\ncd").cursor_position(area), Position::new(3, 2));
+        assert_eq!(typed("abcd").cursor_position(area), Position::new(1, 2));
+        let mut input = typed("abcdef");
+        input.move_cursor_left();
+        input.move_cursor_left();
+        input.move_cursor_left();
+        assert_eq!(input.cursor_position(area), Position::new(4, 1));
+    }
+
+    #[test]
+    fn test_content_scrolls_to_keep_the_cursor_in_view() {
+        let area = Rect::new(0, 0, 6, 4);
+        let input = typed("ab\ncd\nef");
+        assert_eq!(input.cursor_position(area), Position::new(3, 2));
+
+        let mut buffer = Buffer::empty(area);
+        input.get_widget(true, area).render(area, &mut buffer);
+        let row = |y| (1..5).map(|x| buffer[(x, y)].symbol()).collect::<String>();
+        assert_eq!(row(1), "cd  ");
+        assert_eq!(row(2), "ef  ");
     }
 }
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+// Re-announce while still typing so the peer's indicator doesn't time out
+const TYPING_RESEND: Duration = Duration::from_secs(3);
+const TYPING_IDLE: Duration = Duration::from_secs(5);
+
+// Decides when typing state has to go out, every method returns the state to send
+pub struct TypingNotifier {
+    announced: bool,
+    last_announce: Instant,
+    last_edit: Instant,
+}
+
+impl TypingNotifier {
+    pub fn new() -> Self {
+        Self {
+            announced: false,
+            last_announce: Instant::now(),
+            last_edit: Instant::now(),
+        }
+    }
+
+    pub fn edited(&mut self, has_content: bool) -> Option<bool> {
+        if !has_content {
+            return self.stop();
+        }
+        self.last_edit = Instant::now();
+        if self.announced {
+            return None;
+        }
+        self.announce()
+    }
+
+    // Every edit pushes the stop back, so it only goes out once the input has been idle
+    pub fn tick(&mut self) -> Option<bool> {
+        if !self.announced {
+            return None;
+        }
+        if self.last_edit.elapsed() >= TYPING_IDLE {
+            return self.stop();
+        }
+        if self.last_announce.elapsed() >= TYPING_RESEND && self.last_edit > self.last_announce {
+            return self.announce();
+        }
+        None
+    }
+
+    fn announce(&mut self) -> Option<bool> {
+        self.announced = true;
+        self.last_announce = Instant::now();
+        Some(true)
+    }
+
+    pub fn stop(&mut self) -> Option<bool> {
+        if !self.announced {
+            return None;
+        }
+        self.announced = false;
+        Some(false)
+    }
+
+    // The peer clears the indicator itself once the message arrives
+    pub fn sent(&mut self) {
+        self.announced = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Moves the notifier's clock back instead of sleeping
+    fn ago(duration: Duration) -> Instant {
+        Instant::now() - duration
+    }
+
+    #[test]
+    fn test_typing_is_announced_once_then_repeated() {
+        let mut notifier = TypingNotifier::new();
+        assert_eq!(notifier.edited(false), None);
+        assert_eq!(notifier.edited(true), Some(true));
+        let then = ago(TYPING_RESEND);
+        (notifier.last_announce, notifier.last_edit) = (then, then);
+        // Edits never send on their own, the refresh comes from the tick
+        assert_eq!(notifier.tick(), None);
+        assert_eq!(notifier.edited(true), None);
+        assert_eq!(notifier.tick(), Some(true));
+        assert_eq!(notifier.tick(), None);
+        // Clearing the input stops it right away, and only once
+        assert_eq!(notifier.edited(false), Some(false));
+        assert_eq!(notifier.stop(), None);
+    }
+
+    #[test]
+    fn test_typing_times_out_when_idle() {
+        let mut notifier = TypingNotifier::new();
+        notifier.edited(true);
+        notifier.last_edit = ago(TYPING_IDLE - Duration::from_secs(1));
+        assert_eq!(notifier.tick(), None);
+        // An edit restarts the wait
+        notifier.edited(true);
+        assert_eq!(notifier.tick(), None);
+        notifier.last_edit = ago(TYPING_IDLE);
+        assert_eq!(notifier.tick(), Some(false));
+        assert_eq!(notifier.tick(), None);
+    }
+
+    #[test]
+    fn test_sending_ends_typing_without_a_stop() {
+        let mut notifier = TypingNotifier::new();
+        notifier.edited(true);
+        notifier.sent();
+        assert_eq!(notifier.stop(), None);
+        assert_eq!(notifier.edited(true), Some(true));
+    }
+}
@@ -1,50 +1,183 @@
+#![allow(non_snake_case)]
 pub mod ListConfig {
-    use ratatui::{
-        style::{Color, Style, Stylize},
-        widgets::ListDirection,
-    };
+    use ratatui::{style::Style, widgets::ListDirection};
 
-    // Setup editable conf file for this
+    use crate::{networking::presence::Presence, theme};
 
     pub fn unselected_color() -> Style {
-        Style::new().fg(Color::Gray)
+        theme::style("list.unselected")
     }
     pub fn selected_color() -> Style {
-        Style::new().fg(Color::Yellow)
+        theme::style("list.selected")
     }
     pub fn highlight() -> Style {
-        Style::new().bold().italic()
+        theme::style("list.highlight")
     }
 
     pub fn direction() -> ListDirection {
         ListDirection::TopToBottom
     }
+
+    // Invisible peers and dead connections share the offline look
+    pub fn presence_symbol(presence: Presence) -> &'static str {
+        match presence {
+            Presence::Online => "● ",
+            Presence::Away => "◐ ",
+            Presence::Busy => "⊖ ",
+            Presence::Invisible => "○ ",
+        }
+    }
+    pub fn presence_style(presence: Presence) -> Style {
+        theme::style(match presence {
+            Presence::Online => "presence.online",
+            Presence::Away => "presence.away",
+            Presence::Busy => "presence.busy",
+            Presence::Invisible => "presence.invisible",
+        })
+    }
+    pub fn status_style() -> Style {
+        theme::style("list.status")
+    }
+    pub fn unread_style() -> Style {
+        theme::style("list.unread")
+    }
+    pub fn unread_badge_style() -> Style {
+        theme::style("list.unread_badge")
+    }
+    pub fn mention_badge_style() -> Style {
+        theme::style("list.mention_badge")
+    }
 }
 
 pub mod MessageConfig {
-    use ratatui::style::{Color, Style, Stylize};
+    use ratatui::style::Style;
+
+    use crate::theme;
 
     pub fn username_style(is_from_client: bool) -> Style {
         if is_from_client {
-            Style::new().fg(Color::LightYellow).bold()
+            theme::style("message.username.self")
         } else {
-            Style::new().fg(Color::Yellow).bold()
+            theme::style("message.username.peer")
         }
     }
     pub fn time_style() -> Style {
-        Style::new().fg(Color::Gray).italic()
+        theme::style("message.time")
+    }
+    pub fn focused_time_style() -> Style {
+        theme::style("message.time.focused")
+    }
+    pub fn search_match_style() -> Style {
+        theme::style("message.search_match")
     }
     pub fn text_style() -> Style {
-        Style::new().fg(Color::White)
+        theme::style("message.text")
+    }
+    pub fn error_style() -> Style {
+        theme::style("message.error")
+    }
+    pub fn typing_style() -> Style {
+        theme::style("message.typing")
+    }
+    pub fn unread_marker_style() -> Style {
+        theme::style("message.unread_marker")
+    }
+    pub fn mention_style() -> Style {
+        theme::style("message.mention")
+    }
+    pub fn code_style() -> Style {
+        theme::style("code.inline")
+    }
+    pub fn code_block_style() -> Style {
+        theme::style("code.block")
+    }
+    pub fn code_comment_style() -> Style {
+        theme::style("code.comment")
+    }
+    pub fn code_string_style() -> Style {
+        theme::style("code.string")
+    }
+    pub fn code_constant_style() -> Style {
+        theme::style("code.constant")
+    }
+    pub fn code_keyword_style() -> Style {
+        theme::style("code.keyword")
+    }
+    pub fn code_function_style() -> Style {
+        theme::style("code.function")
+    }
+    pub fn code_type_style() -> Style {
+        theme::style("code.type")
+    }
+    pub fn reaction_style(is_from_client: bool) -> Style {
+        if is_from_client {
+            theme::style("message.reaction.self")
+        } else {
+            theme::style("message.reaction.peer")
+        }
+    }
+    pub fn tombstone_style() -> Style {
+        theme::style("message.tombstone")
+    }
+    pub fn link_style() -> Style {
+        theme::style("message.link")
+    }
+    pub fn quote_style() -> Style {
+        theme::style("message.quote")
+    }
+    pub fn list_bullet_style() -> Style {
+        theme::style("message.list_bullet")
+    }
+    pub fn date_separator_style() -> Style {
+        theme::style("message.date_separator")
+    }
+    // Border of the pane the input sends to, once there is more than one
+    pub fn focused_pane_style() -> Style {
+        theme::style("message.focused_pane")
     }
 }
 pub mod InputConfig {
-    use ratatui::style::{Color, Style};
+    use ratatui::style::Style;
+
+    use crate::theme;
 
     pub fn unselected_color() -> Style {
-        Style::new().fg(Color::Gray)
+        theme::style("input.unselected")
     }
     pub fn selected_color() -> Style {
-        Style::new().fg(Color::Yellow)
+        theme::style("input.selected")
+    }
+}
+
+pub mod StatusConfig {
+    use ratatui::style::Style;
+
+    use crate::theme;
+
+    pub fn bar_style() -> Style {
+        theme::style("status.bar")
+    }
+    pub fn state_style() -> Style {
+        theme::style("status.state")
+    }
+    pub fn key_style() -> Style {
+        theme::style("status.key")
+    }
+    pub fn unencrypted_style() -> Style {
+        theme::style("status.unencrypted")
+    }
+    pub fn heading_style() -> Style {
+        theme::style("status.heading")
+    }
+}
+
+// Background for everything drawn over the main panes
+pub mod PopupConfig {
+    use ratatui::style::Style;
+
+    use crate::theme;
+
+    pub fn style() -> Style {
+        theme::style("popup")
     }
 }
@@ -1,36 +1,106 @@
 use ratatui::{
-    crossterm::event::{self, KeyCode, KeyEventKind},
-    layout::{Constraint, Layout},
-    style::{Style, Stylize},
-    widgets::{Block, List, ListDirection, ListState, Paragraph},
+    crossterm::{
+        event::{
+            self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture,
+            EnableBracketedPaste, EnableFocusChange, EnableMouseCapture,
+        },
+        execute,
+    },
     DefaultTerminal,
 };
 use std::{
-    io,
-    sync::{Arc, Mutex, MutexGuard},
+    io::{self, stdout},
+    panic,
+    time::Duration,
 };
-mod config;
-//use crate::networking::Connection;
-use config::ListConfig;
 mod app;
+mod config;
+use crate::{
+    networking::identity::Identity,
+    settings::Settings,
+    theme::{self, Themes},
+};
 use app::{App, AppState};
-pub fn start() -> io::Result<()> {
+
+// Redraw at least this often so incoming messages show without a keypress
+const TICK_RATE: Duration = Duration::from_millis(100);
+pub fn start(settings: Settings) -> io::Result<()> {
+    // A broken theme is reported like a broken config, before the screen is taken over
+    let themes = Themes::new(Settings::themes_dir(), settings.theme.colors);
+    theme::set(
+        themes
+            .load(&settings.theme.name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+    );
+    let identity = Identity::load_or_create(&Settings::identity_path())?;
+    let mouse = settings.input.mouse;
     let mut terminal = ratatui::init();
+    // ratatui's hook restores the screen, the modes we turned on are ours to undo
+    let restore_screen = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        TerminalGuard::disable_modes(mouse);
+        restore_screen(info);
+    }));
+    let _guard = TerminalGuard::new(mouse)?;
     terminal.clear()?;
-    let app_result = run(terminal);
-    ratatui::restore();
-    app_result
+    run(terminal, settings, themes, identity)
+}
+
+// Hands the terminal back the way we found it however start returns, errors included
+struct TerminalGuard {
+    mouse: bool,
+}
+
+impl TerminalGuard {
+    // The guard exists before anything is enabled, so a failure halfway is undone too
+    fn new(mouse: bool) -> io::Result<TerminalGuard> {
+        let guard = TerminalGuard { mouse };
+        execute!(stdout(), EnableFocusChange, EnableBracketedPaste)?;
+        if mouse {
+            execute!(stdout(), EnableMouseCapture)?;
+        }
+        Ok(guard)
+    }
+
+    // Each is attempted even if another fails, nothing else can be done about it this late
+    fn disable_modes(mouse: bool) {
+        if mouse {
+            let _ = execute!(stdout(), DisableMouseCapture);
+        }
+        let _ = execute!(stdout(), DisableBracketedPaste);
+        let _ = execute!(stdout(), DisableFocusChange);
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        TerminalGuard::disable_modes(self.mouse);
+        ratatui::restore();
+    }
 }
 
-fn run(mut terminal: DefaultTerminal) -> io::Result<()> {
-    let mut app = App::new();
+fn run(
+    mut terminal: DefaultTerminal,
+    settings: Settings,
+    themes: Themes,
+    identity: Identity,
+) -> io::Result<()> {
+    let mut app = App::new(settings, themes, identity);
 
     while app.state != AppState::Closing {
         app.update_connection_list();
         terminal.draw(|frame| app.render(frame))?;
-        if let event::Event::Key(key) = event::read()? {
-            app.handle_input(&key);
+        if event::poll(TICK_RATE)? {
+            match event::read()? {
+                event::Event::Key(key) => app.handle_input(&key),
+                event::Event::Paste(text) => app.handle_paste(text),
+                event::Event::Mouse(mouse) => app.handle_mouse(&mouse),
+                event::Event::FocusGained => app.set_focused(true),
+                event::Event::FocusLost => app.set_focused(false),
+                _ => {}
+            }
         }
+        app.tick()?;
     }
     Ok(())
 }